@@ -0,0 +1,45 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/activity.rs
+ */
+
+use crate::get_active_window::WindowInfo;
+use crate::get_media::MediaMetadata;
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+
+/// 一次检测得到的活动信息，是各上报目标生成 payload 的数据来源
+#[derive(Debug, Clone, PartialEq)]
+pub struct Activity {
+    pub process: String,
+    pub extend: String,
+    /// 为 None 表示不附带媒体信息
+    pub media: Option<MediaMetadata>,
+    pub timestamp: i64,
+    /// 检测到的窗口，供隐私规则匹配；不会出现在上报内容中。占位上报时为 None
+    pub window: Option<WindowInfo>,
+}
+
+impl Activity {
+    /// 模板中可用的字段
+    pub fn to_json(&self) -> Value {
+        let time = Utc
+            .timestamp_opt(self.timestamp, 0)
+            .single()
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_default();
+
+        json!({
+            "process": self.process,
+            "extend": self.extend,
+            "timestamp": self.timestamp,
+            "time": time,
+            "media": self.media.as_ref().map(|media| json!({
+                "title": media.title.clone().unwrap_or_default(),
+                "artist": media.artist.clone().unwrap_or_default(),
+                "thumbnail": media.thumbnail.clone().unwrap_or_default(),
+            })),
+        })
+    }
+}
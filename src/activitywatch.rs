@@ -0,0 +1,411 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/activitywatch.rs
+ */
+
+use crate::config::ActivityWatchConfig;
+use crate::get_active_window::WindowInfo;
+use crate::history::Span;
+use chrono::{DateTime, Duration, Utc};
+use reqwest::header;
+use serde_json::{json, Value};
+use std::error::Error;
+use tracing::{info, warn};
+
+const CLIENT_NAME: &str = "processforlinux";
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+/// 本机主机名，ActivityWatch 按主机名区分设备
+pub fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// ActivityWatch 的一个事件，duration 单位为秒
+fn event(timestamp: DateTime<Utc>, duration: Duration, data: Value) -> Value {
+    json!({
+        "timestamp": timestamp.to_rfc3339(),
+        "duration": duration.num_milliseconds() as f64 / 1000.0,
+        "data": data,
+    })
+}
+
+fn window_data(app: &str, class: &str, title: &str) -> Value {
+    json!({
+        "app": if app.is_empty() { class } else { app },
+        "title": title,
+    })
+}
+
+/// 作为 ActivityWatch watcher 运行：向 aw-server 发送 currentwindow 和 afkstatus 心跳，
+/// aw-server 会把 pulsetime 内相同的心跳合并成一个事件
+pub struct ActivityWatch {
+    client: reqwest::Client,
+    url: String,
+    hostname: String,
+    window_bucket: String,
+    afk_bucket: String,
+    afk_timeout: Duration,
+    titles: bool,
+    /// 窗口心跳的合并间隔，略大于检测间隔
+    window_pulsetime: f64,
+    buckets_ready: bool,
+    afk: bool,
+    /// 最近一次的错误，只在错误变化时打印，避免 aw-server 没启动时刷屏
+    last_error: Option<String>,
+}
+
+impl ActivityWatch {
+    pub fn new(config: &ActivityWatchConfig, watch_time: i64) -> Self {
+        let hostname = config.hostname.clone().unwrap_or_else(hostname);
+        ActivityWatch {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            url: config.url.trim_end_matches('/').to_string(),
+            window_bucket: config.window_bucket(&hostname),
+            afk_bucket: config.afk_bucket(&hostname),
+            hostname,
+            afk_timeout: Duration::seconds(config.afk_timeout as i64),
+            titles: config.titles,
+            window_pulsetime: (watch_time + 1) as f64,
+            buckets_ready: false,
+            afk: false,
+            last_error: None,
+        }
+    }
+
+    /// 每次检测后调用；window 为 None（暂停、检测失败）时只发送 AFK 状态
+    pub async fn heartbeat(
+        &mut self,
+        now: DateTime<Utc>,
+        window: Option<&WindowInfo>,
+        idle: Option<std::time::Duration>,
+    ) {
+        let result = self.send_heartbeats(now, window, idle).await;
+        match result {
+            Ok(()) => {
+                if self.last_error.take().is_some() {
+                    info!("Connected to ActivityWatch at {}", self.url);
+                }
+            }
+            Err(e) => {
+                // aw-server 可能重启过，下次重新创建 bucket
+                self.buckets_ready = false;
+                let e = e.to_string();
+                if self.last_error.as_ref() != Some(&e) {
+                    warn!("Failed to send heartbeat to ActivityWatch: {}", e);
+                }
+                self.last_error = Some(e);
+            }
+        }
+    }
+
+    async fn send_heartbeats(
+        &mut self,
+        now: DateTime<Utc>,
+        window: Option<&WindowInfo>,
+        idle: Option<std::time::Duration>,
+    ) -> Result<(), Box<dyn Error>> {
+        if !self.buckets_ready {
+            self.create_bucket(&self.window_bucket, "currentwindow").await?;
+            self.create_bucket(&self.afk_bucket, "afkstatus").await?;
+            self.buckets_ready = true;
+        }
+
+        if let Some(window) = window.filter(|window| !window.class.is_empty() || !window.process.is_empty()) {
+            let title = if self.titles { window.title.as_str() } else { "" };
+            let data = window_data(&window.process, &window.class, title);
+            self.send(&self.window_bucket, self.window_pulsetime, event(now, Duration::zero(), data))
+                .await?;
+        }
+
+        // 与 aw-watcher-afk 相同：状态变化时在最后一次输入的时间点切换
+        let Some(idle) = idle.and_then(|idle| Duration::from_std(idle).ok()) else {
+            return Ok(());
+        };
+        let last_input = now - idle;
+        let pulsetime = (self.afk_timeout + Duration::seconds(self.window_pulsetime as i64)).num_seconds() as f64;
+        let status = |afk: bool| json!({ "status": if afk { "afk" } else { "not-afk" } });
+        let one_ms = Duration::milliseconds(1);
+
+        let heartbeats = if self.afk && idle < self.afk_timeout {
+            self.afk = false;
+            info!("Back from AFK");
+            vec![
+                event(last_input - one_ms, Duration::zero(), status(true)),
+                event(last_input, Duration::zero(), status(false)),
+            ]
+        } else if !self.afk && idle >= self.afk_timeout {
+            self.afk = true;
+            info!("AFK since {}", last_input.format("%H:%M:%S"));
+            vec![
+                event(last_input - one_ms, Duration::zero(), status(false)),
+                event(last_input, idle, status(true)),
+            ]
+        } else if self.afk {
+            vec![event(last_input, idle, status(true))]
+        } else {
+            vec![event(now, Duration::zero(), status(false))]
+        };
+        for heartbeat in heartbeats {
+            self.send(&self.afk_bucket, pulsetime, heartbeat).await?;
+        }
+        Ok(())
+    }
+
+    async fn create_bucket(&self, bucket: &str, kind: &str) -> Result<(), Box<dyn Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/0/buckets/{}", self.url, bucket))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(
+                json!({
+                    "client": CLIENT_NAME,
+                    "type": kind,
+                    "hostname": self.hostname,
+                })
+                .to_string(),
+            )
+            .send()
+            .await?;
+        // 已经存在时返回 304
+        if !response.status().is_success() && response.status().as_u16() != 304 {
+            return Err(format!("creating bucket {} failed: HTTP {}", bucket, response.status()).into());
+        }
+        Ok(())
+    }
+
+    async fn send(&self, bucket: &str, pulsetime: f64, event: Value) -> Result<(), Box<dyn Error>> {
+        let response = self
+            .client
+            .post(format!("{}/api/0/buckets/{}/heartbeat", self.url, bucket))
+            .query(&[("pulsetime", pulsetime)])
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(event.to_string())
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("heartbeat to {} failed: HTTP {}", bucket, response.status()).into());
+        }
+        Ok(())
+    }
+}
+
+/// 把本地历史导出为 aw-server 的 bucket 导出格式，可以用 aw-server 的 /api/0/import 导入
+pub fn export(spans: &[Span], config: &ActivityWatchConfig) -> Value {
+    let hostname = config.hostname.clone().unwrap_or_else(hostname);
+    let bucket = config.window_bucket(&hostname);
+    let events: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let title = if config.titles { span.title.as_str() } else { "" };
+            event(span.start, span.end - span.start, window_data(&span.app, &span.class, title))
+        })
+        .collect();
+    let created = spans.first().map_or_else(Utc::now, |span| span.start);
+
+    json!({
+        "buckets": {
+            bucket.clone(): {
+                "id": bucket,
+                "created": created.to_rfc3339(),
+                "type": "currentwindow",
+                "client": CLIENT_NAME,
+                "hostname": hostname,
+                "events": events,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    /// bucket 名 → 合并后的事件
+    type Buckets = Arc<Mutex<BTreeMap<String, Vec<Value>>>>;
+
+    fn seconds(event: &Value) -> (DateTime<Utc>, f64) {
+        let start = DateTime::parse_from_rfc3339(event["timestamp"].as_str().unwrap()).unwrap();
+        (start.with_timezone(&Utc), event["duration"].as_f64().unwrap())
+    }
+
+    /// 与 aw-server 相同的心跳合并：数据相同且在上一个事件结束后 pulsetime 秒内时延长上一个事件
+    fn merge(events: &mut Vec<Value>, heartbeat: Value, pulsetime: f64) {
+        if let Some(last) = events.last_mut().filter(|last| last["data"] == heartbeat["data"]) {
+            let (start, duration) = seconds(last);
+            let (time, length) = seconds(&heartbeat);
+            let offset = (time - start).num_milliseconds() as f64 / 1000.0;
+            if offset >= 0.0 && offset <= duration + pulsetime {
+                last["duration"] = json!(duration.max(offset + length));
+                return;
+            }
+        }
+        events.push(heartbeat);
+    }
+
+    /// 本地的假 aw-server：记录创建的 bucket，按 pulsetime 合并心跳
+    async fn mock_server(buckets: Buckets) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let buckets = buckets.clone();
+                tokio::spawn(async move {
+                    let mut reader = BufReader::new(stream);
+                    loop {
+                        let mut request_line = String::new();
+                        if reader.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let mut length = 0;
+                        loop {
+                            let mut line = String::new();
+                            reader.read_line(&mut line).await.unwrap();
+                            let line = line.trim_end();
+                            if line.is_empty() {
+                                break;
+                            }
+                            if let Some((name, value)) = line.split_once(':') {
+                                if name.eq_ignore_ascii_case("content-length") {
+                                    length = value.trim().parse().unwrap();
+                                }
+                            }
+                        }
+                        let mut body = vec![0; length];
+                        reader.read_exact(&mut body).await.unwrap();
+                        let body: Value = serde_json::from_slice(&body).unwrap();
+
+                        let target = request_line.split(' ').nth(1).unwrap();
+                        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+                        let bucket = path.trim_start_matches("/api/0/buckets/");
+                        {
+                            let mut buckets = buckets.lock().unwrap();
+                            match bucket.strip_suffix("/heartbeat") {
+                                Some(bucket) => {
+                                    let pulsetime = query.trim_start_matches("pulsetime=").parse().unwrap();
+                                    merge(buckets.get_mut(bucket).unwrap(), body, pulsetime);
+                                }
+                                None => {
+                                    assert_eq!(body["client"], CLIENT_NAME);
+                                    buckets.entry(bucket.to_string()).or_default();
+                                }
+                            }
+                        }
+                        let response = "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n";
+                        reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    fn config(url: String) -> ActivityWatchConfig {
+        ActivityWatchConfig {
+            enable: true,
+            url,
+            hostname: Some("test".to_string()),
+            afk_timeout: 180,
+            ..Default::default()
+        }
+    }
+
+    /// 事件的 (开始秒数, 时长毫秒, data 中的一个字段)
+    fn summary(events: &[Value], start: DateTime<Utc>, field: &str) -> Vec<(i64, i64, String)> {
+        events
+            .iter()
+            .map(|event| {
+                let (time, duration) = seconds(event);
+                (
+                    (time - start).num_milliseconds(),
+                    (duration * 1000.0).round() as i64,
+                    event["data"][field].as_str().unwrap().to_string(),
+                )
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn merges_heartbeats_and_tracks_afk() {
+        let buckets = Buckets::default();
+        let url = mock_server(buckets.clone()).await;
+        let mut watcher = ActivityWatch::new(&config(url), 5);
+        let code = WindowInfo::new("code", "main.rs", None);
+        let firefox = WindowInfo::new("firefox", "docs", None);
+        let start = Utc::now() - Duration::hours(1);
+        let at = |secs| start + Duration::seconds(secs);
+        let idle = |secs| Some(std::time::Duration::from_secs(secs));
+
+        for (secs, window) in [(0, &code), (5, &code), (10, &code), (15, &firefox), (40, &firefox)] {
+            watcher.heartbeat(at(secs), Some(window), idle(0)).await;
+            assert_eq!(watcher.last_error, None);
+        }
+        // 200 秒没有输入，超过 afk_timeout 后在最后一次输入时切换为 afk
+        watcher.heartbeat(at(300), None, idle(200)).await;
+        assert!(watcher.afk);
+        // 有输入后切换回来
+        watcher.heartbeat(at(310), None, idle(3)).await;
+        assert!(!watcher.afk);
+
+        let buckets = buckets.lock().unwrap();
+        assert_eq!(
+            buckets.keys().collect::<Vec<_>>(),
+            ["aw-watcher-afk_test", "aw-watcher-window_test"]
+        );
+        // 检测间隔内的相同窗口合并为一个事件，间隔超过 pulsetime 时另起一个
+        assert_eq!(
+            summary(&buckets["aw-watcher-window_test"], start, "title"),
+            [
+                (0, 10_000, "main.rs".to_string()),
+                (15_000, 0, "docs".to_string()),
+                (40_000, 0, "docs".to_string()),
+            ]
+        );
+        assert_eq!(
+            summary(&buckets["aw-watcher-afk_test"], start, "status"),
+            [
+                (0, 99_999, "not-afk".to_string()),
+                (100_000, 206_999, "afk".to_string()),
+                (307_000, 0, "not-afk".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn exports_spans_as_window_bucket() {
+        let start = Utc::now() - Duration::hours(1);
+        let span = |app: &str, class: &str, offset: i64, length: i64| Span {
+            start: start + Duration::seconds(offset),
+            end: start + Duration::seconds(offset + length),
+            app: app.to_string(),
+            class: class.to_string(),
+            exe: String::new(),
+            title: "secret".to_string(),
+            media: None,
+        };
+        let mut config = config(String::new());
+        config.titles = false;
+
+        let export = export(&[span("Code", "code", 0, 90), span("", "kitty", 90, 30)], &config);
+        let bucket = &export["buckets"]["aw-watcher-window_test"];
+        assert_eq!(bucket["id"], "aw-watcher-window_test");
+        assert_eq!(bucket["type"], "currentwindow");
+        assert_eq!(bucket["client"], CLIENT_NAME);
+        assert_eq!(bucket["hostname"], "test");
+        assert_eq!(bucket["created"], start.to_rfc3339());
+        let events = bucket["events"].as_array().unwrap();
+        // 未知应用使用窗口类名，titles = false 时不导出标题
+        assert_eq!(summary(events, start, "app"), [(0, 90_000, "Code".to_string()), (90_000, 30_000, "kitty".to_string())]);
+        assert!(events.iter().all(|event| event["data"]["title"] == ""));
+    }
+}
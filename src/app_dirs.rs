@@ -0,0 +1,55 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/app_dirs.rs
+ */
+
+use std::env;
+use std::path::PathBuf;
+
+const APP_NAME: &str = "processforlinux";
+
+/// 按 XDG 规范解析目录：优先使用环境变量，否则回退到 $HOME 下的默认位置
+fn xdg_dir(var: &str, home_fallback: &str) -> PathBuf {
+    if let Some(dir) = env::var_os(var).filter(|v| !v.is_empty()) {
+        return PathBuf::from(dir);
+    }
+
+    let home = env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir);
+    home.join(home_fallback)
+}
+
+/// 状态目录，例如 ~/.local/state/processforlinux
+pub fn state_dir() -> PathBuf {
+    xdg_dir("XDG_STATE_HOME", ".local/state").join(APP_NAME)
+}
+
+/// 数据目录，例如 ~/.local/share/processforlinux
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share").join(APP_NAME)
+}
+
+/// 配置目录，例如 ~/.config/processforlinux
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join(APP_NAME)
+}
+
+/// systemd 用户单元目录，例如 ~/.config/systemd/user
+pub fn systemd_user_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("systemd/user")
+}
+
+/// XDG 自启动目录，例如 ~/.config/autostart
+pub fn autostart_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config").join("autostart")
+}
+
+/// 运行时目录，例如 /run/user/1000/processforlinux，没有 $XDG_RUNTIME_DIR 时使用状态目录
+pub fn runtime_dir() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join(APP_NAME),
+        None => state_dir(),
+    }
+}
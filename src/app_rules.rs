@@ -0,0 +1,165 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/app_rules.rs
+ */
+
+use crate::activity::Activity;
+use crate::config::{AppRuleConfig, RulesConfig};
+use crate::get_active_window::WindowInfo;
+use regex::Regex;
+
+/// 规则可用的处理方式
+pub const RULE_ACTIONS: [&str; 3] = ["drop", "label", "busy"];
+
+/// 应用命中规则后的处理方式
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuleAction {
+    /// 这次不上报
+    Drop,
+    /// 进程名替换为指定文字，清空 extend
+    Label(String),
+    /// 上报为忙碌，同时去掉 extend 和媒体信息
+    Busy(String),
+}
+
+impl RuleAction {
+    fn parse(action: &str, label: Option<&str>, rules: &RulesConfig) -> Result<Self, String> {
+        match action.trim().to_lowercase().as_str() {
+            "drop" => Ok(RuleAction::Drop),
+            "label" => Ok(RuleAction::Label(
+                label.unwrap_or(&rules.unlisted_label).to_string(),
+            )),
+            "busy" => Ok(RuleAction::Busy(label.unwrap_or(&rules.busy_label).to_string())),
+            action => Err(format!(
+                "unknown rule action '{}', expected one of: {}",
+                action,
+                RULE_ACTIONS.join(", ")
+            )),
+        }
+    }
+}
+
+/// 编译好的一条规则
+#[derive(Debug, Clone)]
+struct Matcher {
+    class: Option<String>,
+    exe: Option<String>,
+    title: Option<Regex>,
+    /// 原始写法，用于 dry-run 中说明命中了哪条规则
+    description: String,
+}
+
+// Regex 没有实现 PartialEq，按原始写法比较
+impl PartialEq for Matcher {
+    fn eq(&self, other: &Self) -> bool {
+        self.description == other.description
+    }
+}
+
+impl Matcher {
+    fn compile(rule: &AppRuleConfig) -> Result<Self, String> {
+        if rule.class.is_none() && rule.exe.is_none() && rule.title.is_none() {
+            return Err(format!("rule '{}' needs at least one of class, exe or title", rule));
+        }
+        let title = match &rule.title {
+            Some(pattern) => Some(
+                Regex::new(pattern).map_err(|e| format!("invalid title regex '{}': {}", pattern, e))?,
+            ),
+            None => None,
+        };
+        Ok(Matcher {
+            class: rule.class.clone(),
+            exe: rule.exe.clone(),
+            title,
+            description: rule.to_string(),
+        })
+    }
+
+    fn matches(&self, window: &WindowInfo) -> bool {
+        let same = |expected: &Option<String>, actual: &str| {
+            expected.as_ref().is_none_or(|expected| expected.eq_ignore_ascii_case(actual))
+        };
+        same(&self.class, &window.class)
+            && same(&self.exe, &window.exe)
+            && self.title.as_ref().is_none_or(|title| title.is_match(&window.title))
+    }
+}
+
+/// 一个目标生效的全部应用规则：全局规则加上目标自己的规则
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AppRules {
+    /// 每个非空的 allow 列表都要匹配，所以目标的 allow 只能收紧全局的 allow
+    allow: Vec<Vec<Matcher>>,
+    unlisted: Option<RuleAction>,
+    /// 目标自己的 deny 在前，先匹配到的生效
+    deny: Vec<(Matcher, RuleAction)>,
+}
+
+impl AppRules {
+    pub fn compile(
+        rules: &RulesConfig,
+        target_allow: &[AppRuleConfig],
+        target_deny: &[AppRuleConfig],
+    ) -> Result<Self, String> {
+        let compile_all = |rules: &[AppRuleConfig]| {
+            rules.iter().map(Matcher::compile).collect::<Result<Vec<_>, String>>()
+        };
+        let allow: Vec<Vec<Matcher>> = [compile_all(&rules.allow)?, compile_all(target_allow)?]
+            .into_iter()
+            .filter(|list| !list.is_empty())
+            .collect();
+        let unlisted = RuleAction::parse(&rules.unlisted, None, rules)
+            .map_err(|e| format!("unlisted: {}", e))?;
+
+        let mut deny = Vec::new();
+        for rule in target_deny.iter().chain(&rules.deny) {
+            let action = RuleAction::parse(
+                rule.action.as_deref().unwrap_or("drop"),
+                rule.label.as_deref(),
+                rules,
+            )?;
+            deny.push((Matcher::compile(rule)?, action));
+        }
+
+        Ok(AppRules {
+            unlisted: (!allow.is_empty()).then_some(unlisted),
+            allow,
+            deny,
+        })
+    }
+
+    /// 检查窗口是否命中规则，返回处理方式和原因；None 表示照常上报
+    pub fn check(&self, window: &WindowInfo) -> Option<(&RuleAction, String)> {
+        if let Some((matcher, action)) = self.deny.iter().find(|(matcher, _)| matcher.matches(window)) {
+            return Some((action, format!("deny rule '{}'", matcher.description)));
+        }
+        let listed = self
+            .allow
+            .iter()
+            .all(|list| list.iter().any(|matcher| matcher.matches(window)));
+        match &self.unlisted {
+            Some(action) if !listed => Some((action, "not in allow list".to_string())),
+            _ => None,
+        }
+    }
+
+    /// 按规则处理一次活动，Err 为这次被丢弃的原因；命中 label 或 busy 时去掉 extend 和窗口信息
+    pub fn apply(&self, activity: &Activity) -> Result<Activity, String> {
+        let Some((action, reason)) = activity.window.as_ref().and_then(|window| self.check(window)) else {
+            return Ok(activity.clone());
+        };
+        let mut activity = activity.clone();
+        match action {
+            RuleAction::Drop => return Err(format!("dropped by {}", reason)),
+            RuleAction::Label(label) => activity.process = label.clone(),
+            RuleAction::Busy(label) => {
+                activity.process = label.clone();
+                activity.media = None;
+            }
+        }
+        activity.extend.clear();
+        activity.window = None;
+        Ok(activity)
+    }
+}
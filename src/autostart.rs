@@ -0,0 +1,81 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/autostart.rs
+ */
+
+use crate::app_dirs;
+use crate::get_env_file;
+use clap::ArgMatches;
+use std::error::Error;
+use std::path::PathBuf;
+
+const FILE_NAME: &str = "processforlinux.desktop";
+
+/// 自启动项位置：$XDG_CONFIG_HOME/autostart/processforlinux.desktop
+fn entry_path() -> PathBuf {
+    app_dirs::autostart_dir().join(FILE_NAME)
+}
+
+/// 桌面项 Exec 中的参数：引号内的 " ` $ \ 要加反斜杠，% 写两次；
+/// Exec 本身又是字符串值，所有反斜杠还要再转义一次
+fn quote(arg: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '"' | '`' | '$' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            '%' => quoted.push_str("%%"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted.replace('\\', "\\\\")
+}
+
+/// install-autostart 子命令：登录桌面时自动启动，参数与当前命令行相同
+pub fn install(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    // 先确认配置有效，避免每次登录都启动失败
+    get_env_file::get_env_file(matches).map_err(|e| format!("Invalid config: {}", e))?;
+    let exec = get_env_file::run_args(matches)?
+        .iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let entry = format!(
+        "[Desktop Entry]
+Type=Application
+Name=Process Report For Linux
+Comment=Report the active window and media to your site
+Exec={}
+Terminal=false
+X-GNOME-Autostart-enabled=true
+X-KDE-autostart-after=panel
+",
+        exec
+    );
+
+    let path = entry_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, entry)?;
+    println!("Wrote {}", path.display());
+    if app_dirs::systemd_user_dir().join("processforlinux.service").exists() {
+        eprintln!("Warning: the systemd user service is also installed, only one of them will keep running");
+    }
+    Ok(())
+}
+
+/// uninstall-autostart 子命令
+pub fn uninstall() -> Result<(), Box<dyn Error>> {
+    let path = entry_path();
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()).into());
+    }
+    std::fs::remove_file(&path)?;
+    println!("Removed {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,1550 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/config.rs
+ */
+
+use crate::app_dirs;
+use crate::app_rules::AppRules;
+use crate::auth::{AuthScheme, Secret};
+use crate::extend::ExtendTexts;
+use crate::pause;
+use crate::schedule::Schedule;
+use crate::secret_store;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml_edit::TableLike;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError(pub String);
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// 支持的上报目的地
+pub const KNOWN_REPORTERS: [&str; 5] = ["mixspace", "webhook", "jsonl", "stdout", "unix"];
+
+/// 环境变量覆盖的前缀，例如 PROCESSFORLINUX_WATCH_TIME
+pub const ENV_PREFIX: &str = "PROCESSFORLINUX_";
+
+/// 可以被环境变量和命令行覆盖的全局配置项
+pub const GLOBAL_KEYS: &[&str] = &[
+    "watch_time",
+    "log_enable",
+    "log.level",
+    "log.format",
+    "log.output",
+    "log.dir",
+    "log.rotation",
+    "log.max_size",
+    "log.keep",
+    "media.enable",
+    "media.players",
+    "gui.enable",
+    "queue.enable",
+    "queue.dir",
+    "queue.max_entries",
+    "queue.max_age",
+    "history.enable",
+    "history.dir",
+    "history.retention_days",
+    "history.titles",
+    "activitywatch.enable",
+    "activitywatch.url",
+    "activitywatch.hostname",
+    "activitywatch.window_bucket",
+    "activitywatch.afk_bucket",
+    "activitywatch.afk_timeout",
+    "activitywatch.titles",
+    "http.enable",
+    "http.bind",
+    "http.token",
+    "http.allow_origin",
+    "extend.language",
+    "extend.builtin",
+    "rules.exclude",
+    "rules.unlisted",
+    "rules.unlisted_label",
+    "rules.busy_label",
+    "privacy.mode",
+    "privacy.process",
+    "privacy.extend",
+    "privacy.pause_minutes",
+    "shutdown.report",
+    "shutdown.process",
+    "shutdown.extend",
+    "shutdown.timeout",
+    "schedule.timezone",
+    "schedule.windows",
+    "schedule.quiet",
+    "schedule.mode",
+    "schedule.process",
+    "schedule.extend",
+];
+
+/// 日志输出格式
+pub const LOG_FORMATS: [&str; 2] = ["text", "json"];
+
+/// 日志输出位置，auto 在 systemd 下使用 journald，否则使用标准错误
+pub const LOG_OUTPUTS: [&str; 4] = ["auto", "stderr", "file", "journald"];
+
+/// 日志文件按时间切分的周期
+pub const LOG_ROTATIONS: [&str; 3] = ["daily", "hourly", "never"];
+
+/// 暂停时的行为：发送占位上报，或者什么都不发
+pub const PRIVACY_MODES: [&str; 2] = ["placeholder", "silent"];
+
+/// 目标的配置项，完整的键为 targets.<name>.<key>，另有 headers.<name>
+pub const TARGET_KEYS: &[&str] = &[
+    "type",
+    "url",
+    "api_key",
+    "path",
+    "media",
+    "extend",
+    "exclude",
+    "template",
+    "template_file",
+    "method",
+    "auth",
+    "auth_header",
+    "auth_user",
+    "schedule.timezone",
+    "schedule.windows",
+    "schedule.quiet",
+    "schedule.mode",
+    "schedule.process",
+    "schedule.extend",
+];
+
+/// 配置值的来源，优先级从高到低：命令行、环境变量、配置文件、默认值
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Default,
+    File(String),
+    Env(String),
+    Cli(String),
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "file {}", path),
+            Source::Env(var) => write!(f, "env {}", var),
+            Source::Cli(flag) => write!(f, "cli {}", flag),
+        }
+    }
+}
+
+/// 默认读取的 MPRIS 播放器
+const DEFAULT_MEDIA_PLAYERS: [&str; 3] = [
+    "org.mpris.MediaPlayer2.yesplaymusic",
+    "org.mpris.MediaPlayer2.netease-cloud-music",
+    "org.mpris.MediaPlayer2.spotify",
+];
+
+/// 完整配置，对应 config.toml
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 检查时间间隔（秒）
+    pub watch_time: i64,
+    /// 旧的日志开关，为 false 且没有设置 log.level 时只输出警告和错误
+    pub log_enable: bool,
+    pub log: LogConfig,
+    pub media: MediaConfig,
+    pub gui: GuiConfig,
+    pub queue: QueueConfig,
+    pub history: HistoryConfig,
+    /// report 子命令使用的应用分类，分类名 → 应用名或窗口类名
+    pub categories: BTreeMap<String, Vec<String>>,
+    pub activitywatch: ActivityWatchConfig,
+    pub http: HttpConfig,
+    pub extend: ExtendConfig,
+    pub rules: RulesConfig,
+    pub privacy: PrivacyConfig,
+    pub shutdown: ShutdownConfig,
+    /// 全局上报时间表，目标可以用自己的 schedule 覆盖
+    pub schedule: Option<ScheduleConfig>,
+    pub targets: Vec<TargetConfig>,
+
+    /// 非默认值的来源，键为 watch_time、targets.<name>.url 这样的完整路径
+    #[serde(skip)]
+    pub sources: BTreeMap<String, Source>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            watch_time: 5,
+            log_enable: true,
+            log: LogConfig::default(),
+            media: MediaConfig::default(),
+            gui: GuiConfig::default(),
+            queue: QueueConfig::default(),
+            history: HistoryConfig::default(),
+            categories: BTreeMap::new(),
+            activitywatch: ActivityWatchConfig::default(),
+            http: HttpConfig::default(),
+            extend: ExtendConfig::default(),
+            rules: RulesConfig::default(),
+            privacy: PrivacyConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            schedule: None,
+            targets: Vec::new(),
+            sources: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// 日志级别或按模块的过滤规则，例如 "info,processforlinux::get_active_window=debug"
+    pub level: Option<String>,
+    /// text 或 json，journald 输出时不使用
+    pub format: String,
+    /// auto、stderr、file 或 journald
+    pub output: String,
+    /// 日志文件目录，默认 $XDG_STATE_HOME/processforlinux/logs
+    pub dir: Option<PathBuf>,
+    /// daily、hourly 或 never
+    pub rotation: String,
+    /// 单个日志文件的大小上限（MB），超过后切分，0 表示不限制
+    pub max_size: u64,
+    /// 保留的旧日志文件个数
+    pub keep: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: None,
+            format: "text".to_string(),
+            output: "auto".to_string(),
+            dir: None,
+            rotation: "daily".to_string(),
+            max_size: 10,
+            keep: 7,
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn dir(&self) -> PathBuf {
+        self.dir
+            .clone()
+            .unwrap_or_else(|| app_dirs::state_dir().join("logs"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MediaConfig {
+    pub enable: bool,
+    /// 按顺序尝试的 MPRIS 服务名
+    pub players: Vec<String>,
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig {
+            enable: true,
+            players: DEFAULT_MEDIA_PLAYERS.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GuiConfig {
+    pub enable: bool,
+}
+
+impl Default for GuiConfig {
+    fn default() -> Self {
+        GuiConfig { enable: true } // 默认启用GUI
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    pub enable: bool,
+    /// 队列目录，默认 $XDG_STATE_HOME/processforlinux
+    pub dir: Option<PathBuf>,
+    pub max_entries: usize,
+    /// 最长保存时间（秒）
+    pub max_age: i64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            enable: true,
+            dir: None,
+            max_entries: 500,
+            max_age: 86400,
+        }
+    }
+}
+
+impl QueueConfig {
+    pub fn dir(&self) -> PathBuf {
+        self.dir.clone().unwrap_or_else(app_dirs::state_dir)
+    }
+}
+
+/// 本地活动历史
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistoryConfig {
+    pub enable: bool,
+    /// 历史目录，默认 $XDG_DATA_HOME/processforlinux/history
+    pub dir: Option<PathBuf>,
+    /// 保留天数，0 表示一直保留
+    pub retention_days: u32,
+    /// 是否记录窗口标题
+    pub titles: bool,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        HistoryConfig {
+            enable: true,
+            dir: None,
+            retention_days: 90,
+            titles: true,
+        }
+    }
+}
+
+impl HistoryConfig {
+    pub fn dir(&self) -> PathBuf {
+        self.dir
+            .clone()
+            .unwrap_or_else(|| app_dirs::data_dir().join("history"))
+    }
+}
+
+/// 作为 ActivityWatch watcher 向 aw-server 发送心跳
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivityWatchConfig {
+    pub enable: bool,
+    pub url: String,
+    /// 默认使用本机主机名
+    pub hostname: Option<String>,
+    /// 默认 aw-watcher-window_<hostname> 和 aw-watcher-afk_<hostname>，这样 aw-webui 可以直接识别
+    pub window_bucket: Option<String>,
+    pub afk_bucket: Option<String>,
+    /// 多久没有输入算作离开（秒）
+    pub afk_timeout: u64,
+    /// 是否发送窗口标题
+    pub titles: bool,
+}
+
+impl Default for ActivityWatchConfig {
+    fn default() -> Self {
+        ActivityWatchConfig {
+            enable: false,
+            url: "http://localhost:5600".to_string(),
+            hostname: None,
+            window_bucket: None,
+            afk_bucket: None,
+            afk_timeout: 180,
+            titles: true,
+        }
+    }
+}
+
+impl ActivityWatchConfig {
+    pub fn window_bucket(&self, hostname: &str) -> String {
+        self.window_bucket
+            .clone()
+            .unwrap_or_else(|| format!("aw-watcher-window_{}", hostname))
+    }
+
+    pub fn afk_bucket(&self, hostname: &str) -> String {
+        self.afk_bucket
+            .clone()
+            .unwrap_or_else(|| format!("aw-watcher-afk_{}", hostname))
+    }
+}
+
+/// 本地 HTTP 接口，给浏览器小组件和 OBS 叠加层使用
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub enable: bool,
+    /// 监听地址，默认只监听本机
+    pub bind: String,
+    /// 明文 token 或 keyring: / file: / env: 引用，未设置时不校验
+    pub token: Option<String>,
+    /// 允许跨域访问的来源，例如 "*"，小组件页面不是由本接口提供时需要
+    pub allow_origin: Option<String>,
+
+    /// 在 finalize 中解析得到
+    #[serde(skip)]
+    pub resolved_token: Secret,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            enable: false,
+            bind: "127.0.0.1:5650".to_string(),
+            token: None,
+            allow_origin: None,
+            resolved_token: Secret::default(),
+        }
+    }
+}
+
+/// extend 字段的文案
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExtendConfig {
+    /// 多语言文案使用的语言，默认按 LANG 选择
+    pub language: Option<String>,
+    /// 没有匹配的自定义文案时是否使用内置文案
+    pub builtin: bool,
+    pub texts: Vec<ExtendTextConfig>,
+}
+
+impl Default for ExtendConfig {
+    fn default() -> Self {
+        ExtendConfig {
+            language: None,
+            builtin: true,
+            texts: Vec::new(),
+        }
+    }
+}
+
+/// 一条自定义文案，app、prefix、regex 三选一
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtendTextConfig {
+    /// 与应用名或窗口类名完全相同（不区分大小写）
+    #[serde(default)]
+    pub app: Option<String>,
+    /// 应用名或窗口类名的前缀
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// 匹配应用名、窗口类名或窗口标题的正则
+    #[serde(default)]
+    pub regex: Option<String>,
+    pub text: ExtendText,
+}
+
+impl std::fmt::Display for ExtendTextConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match (&self.app, &self.prefix, &self.regex) {
+            (Some(app), _, _) => write!(f, "app={}", app),
+            (_, Some(prefix), _) => write!(f, "prefix={}", prefix),
+            (_, _, Some(regex)) => write!(f, "regex={}", regex),
+            _ => write!(f, "(no match)"),
+        }
+    }
+}
+
+/// 文案：一条、随机轮换的多条，或者按语言区分
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ExtendText {
+    Plain(TextChoice),
+    Localized(BTreeMap<String, TextChoice>),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum TextChoice {
+    One(String),
+    Rotation(Vec<String>),
+}
+
+/// 对所有目标生效的规则
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RulesConfig {
+    /// 隐私过滤：这些进程不会发送给任何目标
+    pub exclude: Vec<String>,
+    /// 只上报匹配的应用，为空表示不限制
+    pub allow: Vec<AppRuleConfig>,
+    /// 匹配的应用按规则中的 action 处理
+    pub deny: Vec<AppRuleConfig>,
+    /// 不在 allow 中的应用的处理方式
+    pub unlisted: String,
+    pub unlisted_label: String,
+    /// action = "busy" 时上报的进程名
+    pub busy_label: String,
+}
+
+impl Default for RulesConfig {
+    fn default() -> Self {
+        RulesConfig {
+            exclude: Vec::new(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+            unlisted: "label".to_string(),
+            unlisted_label: "Other".to_string(),
+            busy_label: "Busy".to_string(),
+        }
+    }
+}
+
+/// 一条应用规则，写了的条件都满足才算匹配；class 和 exe 不区分大小写，title 是正则
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppRuleConfig {
+    pub class: Option<String>,
+    pub exe: Option<String>,
+    pub title: Option<String>,
+    /// drop：不上报；label：替换为 label；busy：上报为忙碌。allow 中的规则忽略此项
+    pub action: Option<String>,
+    pub label: Option<String>,
+}
+
+impl std::fmt::Display for AppRuleConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let fields = [
+            ("class", &self.class),
+            ("exe", &self.exe),
+            ("title", &self.title),
+            ("action", &self.action),
+            ("label", &self.label),
+        ];
+        let parts: Vec<String> = fields
+            .iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| format!("{}={}", name, value)))
+            .collect();
+        write!(f, "{}", parts.join(" "))
+    }
+}
+
+fn join_rules(rules: &[AppRuleConfig]) -> String {
+    rules.iter().map(|rule| rule.to_string()).collect::<Vec<_>>().join("; ")
+}
+
+/// 暂停上报（隐私模式）时的行为
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    /// placeholder：发送占位上报；silent：不上报
+    pub mode: String,
+    /// 占位上报的进程名和 extend
+    pub process: String,
+    pub extend: String,
+    /// 信号和悬浮窗暂停按钮的默认时长（分钟），为空表示一直暂停到手动恢复
+    pub pause_minutes: Option<u64>,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            mode: "placeholder".to_string(),
+            process: "Private".to_string(),
+            extend: String::new(),
+            pause_minutes: None,
+        }
+    }
+}
+
+/// 退出（Ctrl+C、SIGTERM、注销、关闭悬浮窗）时的行为
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShutdownConfig {
+    /// 是否向各目标发送一次离线状态，这样站点上不会一直显示退出前的应用
+    pub report: bool,
+    /// 离线状态的进程名和 extend
+    pub process: String,
+    pub extend: String,
+    /// 重发离线队列和发送离线状态最多等待的秒数
+    pub timeout: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            report: true,
+            process: "Offline".to_string(),
+            extend: String::new(),
+            timeout: 5,
+        }
+    }
+}
+
+/// 上报时间表
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScheduleConfig {
+    /// IANA 时区名，例如 Asia/Shanghai，默认使用本机时区
+    pub timezone: Option<String>,
+    /// 允许上报的时间段，例如 "mon-fri 09:00-18:00"，为空表示全天
+    pub windows: Vec<String>,
+    /// 免打扰时间段，优先于 windows
+    pub quiet: Vec<String>,
+    /// 时间表外的行为：placeholder 持续发送固定状态，silent 只在边界发送一次
+    pub mode: String,
+    /// 时间表外的固定状态
+    pub process: String,
+    pub extend: String,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        ScheduleConfig {
+            timezone: None,
+            windows: Vec::new(),
+            quiet: Vec::new(),
+            mode: "silent".to_string(),
+            process: "Offline".to_string(),
+            extend: String::new(),
+        }
+    }
+}
+
+impl ScheduleConfig {
+    /// 时间段列表在环境变量和命令行中用 ; 分隔，因为星期里可能有逗号
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let windows = |value: &str| -> Vec<String> {
+            value
+                .split(';')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        };
+        match key {
+            "timezone" => self.timezone = optional(value),
+            "windows" => self.windows = windows(value),
+            "quiet" => self.quiet = windows(value),
+            "mode" => self.mode = value.trim().to_lowercase(),
+            "process" => self.process = value.to_string(),
+            "extend" => self.extend = value.to_string(),
+            _ => return Err(format!("unknown schedule key '{}'", key)),
+        }
+        Ok(())
+    }
+
+    fn entries(&self) -> Vec<(String, String)> {
+        vec![
+            ("schedule.timezone".to_string(), self.timezone.clone().unwrap_or_default()),
+            ("schedule.windows".to_string(), self.windows.join("; ")),
+            ("schedule.quiet".to_string(), self.quiet.join("; ")),
+            ("schedule.mode".to_string(), self.mode.clone()),
+            ("schedule.process".to_string(), self.process.clone()),
+            ("schedule.extend".to_string(), self.extend.clone()),
+        ]
+    }
+}
+
+/// 单个上报目标的配置
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TargetConfig {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: Option<String>,
+    /// 明文密钥或 keyring: / file: / env: 引用
+    pub api_key: Option<String>,
+    pub path: Option<PathBuf>,
+    // payload 选项
+    #[serde(rename = "media")]
+    pub media_enable: bool,
+    #[serde(rename = "extend")]
+    pub extend_enable: bool,
+    // 隐私过滤：这些进程不会发送给该目标
+    pub exclude: Vec<String>,
+    // 该目标额外的应用规则，与全局规则同时生效
+    pub allow: Vec<AppRuleConfig>,
+    pub deny: Vec<AppRuleConfig>,
+    // 自定义 payload 模板，以及 webhook 的请求方法和请求头
+    pub template: Option<Value>,
+    pub template_file: Option<PathBuf>,
+    pub method: String,
+    pub headers: BTreeMap<String, String>,
+    // 认证方式，未设置时 mixspace 默认 raw，其他默认 none
+    pub auth: Option<String>,
+    pub auth_header: Option<String>,
+    pub auth_user: Option<String>,
+    // 该目标自己的上报时间表，未设置时使用全局的
+    pub schedule: Option<ScheduleConfig>,
+
+    // 以下字段在 finalize 中解析得到
+    #[serde(skip)]
+    pub key: Secret,
+    #[serde(skip)]
+    pub auth_scheme: AuthScheme,
+    #[serde(skip)]
+    pub resolved_schedule: Option<Schedule>,
+    #[serde(skip)]
+    pub resolved_rules: AppRules,
+}
+
+impl Default for TargetConfig {
+    fn default() -> Self {
+        TargetConfig {
+            name: String::new(),
+            kind: "mixspace".to_string(),
+            url: None,
+            api_key: None,
+            path: None,
+            media_enable: true,
+            extend_enable: true,
+            exclude: Vec::new(),
+            allow: Vec::new(),
+            deny: Vec::new(),
+            template: None,
+            template_file: None,
+            method: "POST".to_string(),
+            headers: BTreeMap::new(),
+            auth: None,
+            auth_header: None,
+            auth_user: None,
+            schedule: None,
+            key: Secret::default(),
+            auth_scheme: AuthScheme::None,
+            resolved_schedule: None,
+            resolved_rules: AppRules::default(),
+        }
+    }
+}
+
+impl TargetConfig {
+    pub fn new(name: &str, kind: &str) -> Self {
+        TargetConfig {
+            name: name.to_string(),
+            kind: kind.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// jsonl 上报方式写入的文件，默认在状态目录下
+    pub fn jsonl_path(&self) -> PathBuf {
+        self.path
+            .clone()
+            .unwrap_or_else(|| app_dirs::state_dir().join("activity.jsonl"))
+    }
+
+    /// 按键名设置一项，键名与 config.toml 中 [[targets]] 下的字段一致
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "type" => self.kind = value.trim().to_lowercase(),
+            "url" => self.url = optional(value),
+            "api_key" => self.api_key = optional(value),
+            "path" => self.path = optional(value).map(PathBuf::from),
+            "media" => self.media_enable = parse_value(value)?,
+            "extend" => self.extend_enable = parse_value(value)?,
+            "exclude" => self.exclude = parse_list(value),
+            "template" => {
+                self.template = match optional(value) {
+                    Some(value) => Some(serde_json::from_str(&value).map_err(|e| e.to_string())?),
+                    None => None,
+                }
+            }
+            "template_file" => self.template_file = optional(value).map(PathBuf::from),
+            "method" => self.method = value.trim().to_uppercase(),
+            "auth" => self.auth = optional(value),
+            "auth_header" => self.auth_header = optional(value),
+            "auth_user" => self.auth_user = optional(value),
+            _ if key.starts_with("schedule.") => self
+                .schedule
+                .get_or_insert_with(ScheduleConfig::default)
+                .set(&key["schedule.".len()..], value)?,
+            _ => match key.strip_prefix("headers.") {
+                Some(name) if !name.is_empty() => {
+                    match optional(value) {
+                        Some(value) => self.headers.insert(name.to_string(), value),
+                        None => self.headers.remove(name),
+                    };
+                }
+                _ => return Err(format!("unknown target key '{}'", key)),
+            },
+        }
+        Ok(())
+    }
+
+    /// 当前的所有配置项，用于 config show；明文密钥会被遮盖
+    fn entries(&self) -> Vec<(String, String)> {
+        let path = |path: &Option<PathBuf>| path.as_ref().map(|p| p.display().to_string());
+        let mut entries = vec![
+            ("type", Some(self.kind.clone())),
+            ("url", self.url.clone()),
+            ("api_key", self.api_key.as_deref().map(display_secret)),
+            ("path", path(&self.path)),
+            ("media", Some(self.media_enable.to_string())),
+            ("extend", Some(self.extend_enable.to_string())),
+            ("exclude", Some(self.exclude.join(","))),
+            ("allow", Some(join_rules(&self.allow))),
+            ("deny", Some(join_rules(&self.deny))),
+            ("template", self.template.as_ref().map(|t| t.to_string())),
+            ("template_file", path(&self.template_file)),
+            ("method", Some(self.method.clone())),
+            ("auth", self.auth.clone()),
+            ("auth_header", self.auth_header.clone()),
+            ("auth_user", self.auth_user.clone()),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.unwrap_or_default()))
+        .collect::<Vec<_>>();
+
+        for (name, value) in &self.headers {
+            // 请求头里可能直接写了 token，同样遮盖
+            let lower = name.to_lowercase();
+            let sensitive = ["authorization", "token", "key", "secret"]
+                .iter()
+                .any(|word| lower.contains(word));
+            let value = if sensitive && !value.contains("{{") {
+                Secret::new(value.as_str()).masked().to_string()
+            } else {
+                value.clone()
+            };
+            entries.push((format!("headers.{}", name), value));
+        }
+        if let Some(schedule) = &self.schedule {
+            entries.extend(schedule.entries());
+        }
+        entries
+    }
+
+    /// 校验目标配置，并解析密钥、模板文件和认证方式
+    fn finalize(&mut self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("target is missing 'name'".to_string());
+        }
+        self.kind = self.kind.to_lowercase();
+        if !KNOWN_REPORTERS.contains(&self.kind.as_str()) {
+            return Err(format!(
+                "unknown type '{}', expected one of: {}",
+                self.kind,
+                KNOWN_REPORTERS.join(", ")
+            ));
+        }
+        match self.kind.as_str() {
+            "mixspace" => {
+                if self.url.is_none() {
+                    return Err("'url' is required for type mixspace".to_string());
+                }
+                if self.api_key.is_none() {
+                    return Err("'api_key' is required for type mixspace".to_string());
+                }
+            }
+            "webhook" if self.url.is_none() => {
+                return Err("'url' is required for type webhook".to_string());
+            }
+            "unix" if self.path.is_none() => {
+                return Err("'path' is required for type unix".to_string());
+            }
+            _ => {}
+        }
+
+        if let Some(reference) = &self.api_key {
+            self.key = secret_store::resolve(reference).map_err(|e| e.to_string())?;
+        }
+
+        if let Some(path) = &self.template_file {
+            let source = std::fs::read_to_string(path)
+                .map_err(|e| format!("failed to read template {}: {}", path.display(), e))?;
+            let template = serde_json::from_str(&source)
+                .map_err(|e| format!("invalid template {}: {}", path.display(), e))?;
+            self.template = Some(template);
+        }
+
+        let default_mode = if self.kind == "mixspace" { "raw" } else { "none" };
+        self.auth_scheme = AuthScheme::parse(
+            self.auth.as_deref().unwrap_or(default_mode),
+            self.auth_header.as_deref(),
+            self.auth_user.as_deref(),
+        )?;
+        self.method = self.method.to_uppercase();
+        Ok(())
+    }
+}
+
+impl Config {
+    /// 实际使用的日志过滤规则
+    pub fn log_level(&self) -> &str {
+        match &self.log.level {
+            Some(level) => level,
+            None if self.log_enable => "info",
+            None => "warn",
+        }
+    }
+
+    /// 按完整键名设置一项，例如 watch_time、media.players、targets.blog.url；
+    /// 不存在的目标会被新建，这样可以完全通过环境变量配置
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "watch_time" => self.watch_time = parse_value(value)?,
+            "log_enable" => self.log_enable = parse_value(value)?,
+            "log.level" => self.log.level = optional(value),
+            "log.format" => self.log.format = value.trim().to_lowercase(),
+            "log.output" => self.log.output = value.trim().to_lowercase(),
+            "log.dir" => self.log.dir = optional(value).map(PathBuf::from),
+            "log.rotation" => self.log.rotation = value.trim().to_lowercase(),
+            "log.max_size" => self.log.max_size = parse_value(value)?,
+            "log.keep" => self.log.keep = parse_value(value)?,
+            "media.enable" => self.media.enable = parse_value(value)?,
+            "media.players" => self.media.players = parse_list(value),
+            "gui.enable" => self.gui.enable = parse_value(value)?,
+            "queue.enable" => self.queue.enable = parse_value(value)?,
+            "queue.dir" => self.queue.dir = optional(value).map(PathBuf::from),
+            "queue.max_entries" => self.queue.max_entries = parse_value(value)?,
+            "queue.max_age" => self.queue.max_age = parse_value(value)?,
+            "history.enable" => self.history.enable = parse_value(value)?,
+            "history.dir" => self.history.dir = optional(value).map(PathBuf::from),
+            "history.retention_days" => self.history.retention_days = parse_value(value)?,
+            "history.titles" => self.history.titles = parse_value(value)?,
+            "activitywatch.enable" => self.activitywatch.enable = parse_value(value)?,
+            "activitywatch.url" => self.activitywatch.url = value.trim().to_string(),
+            "activitywatch.hostname" => self.activitywatch.hostname = optional(value),
+            "activitywatch.window_bucket" => self.activitywatch.window_bucket = optional(value),
+            "activitywatch.afk_bucket" => self.activitywatch.afk_bucket = optional(value),
+            "activitywatch.afk_timeout" => self.activitywatch.afk_timeout = parse_value(value)?,
+            "activitywatch.titles" => self.activitywatch.titles = parse_value(value)?,
+            "http.enable" => self.http.enable = parse_value(value)?,
+            "http.bind" => self.http.bind = value.trim().to_string(),
+            "http.token" => self.http.token = optional(value),
+            "http.allow_origin" => self.http.allow_origin = optional(value),
+            "extend.language" => self.extend.language = optional(value),
+            "extend.builtin" => self.extend.builtin = parse_value(value)?,
+            "rules.exclude" => self.rules.exclude = parse_list(value),
+            "rules.unlisted" => self.rules.unlisted = value.trim().to_lowercase(),
+            "rules.unlisted_label" => self.rules.unlisted_label = value.to_string(),
+            "rules.busy_label" => self.rules.busy_label = value.to_string(),
+            "privacy.mode" => self.privacy.mode = value.trim().to_lowercase(),
+            "privacy.process" => self.privacy.process = value.to_string(),
+            "privacy.extend" => self.privacy.extend = value.to_string(),
+            _ if key.starts_with("categories.") => {
+                let name = &key["categories.".len()..];
+                match parse_list(value) {
+                    apps if apps.is_empty() => self.categories.remove(name),
+                    apps => self.categories.insert(name.to_string(), apps),
+                };
+            }
+            _ if key.starts_with("schedule.") => self
+                .schedule
+                .get_or_insert_with(ScheduleConfig::default)
+                .set(&key["schedule.".len()..], value)?,
+            "shutdown.report" => self.shutdown.report = parse_value(value)?,
+            "shutdown.process" => self.shutdown.process = value.to_string(),
+            "shutdown.extend" => self.shutdown.extend = value.to_string(),
+            "shutdown.timeout" => self.shutdown.timeout = parse_value(value)?,
+            "privacy.pause_minutes" => {
+                self.privacy.pause_minutes = match optional(value) {
+                    Some(value) => Some(parse_value(&value)?),
+                    None => None,
+                }
+            }
+            _ => {
+                let Some((name, field)) = key
+                    .strip_prefix("targets.")
+                    .and_then(|rest| rest.split_once('.'))
+                else {
+                    return Err(format!("unknown config key '{}'", key));
+                };
+                let index = match self.targets.iter().position(|target| target.name == name) {
+                    Some(index) => index,
+                    None => {
+                        self.targets.push(TargetConfig::new(name, "mixspace"));
+                        self.targets.len() - 1
+                    }
+                };
+                self.targets[index].set(field, value)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 设置一项并记录来源
+    pub fn set_from(&mut self, key: &str, value: &str, source: Source) -> Result<(), ConfigError> {
+        self.set(key, value)
+            .map_err(|e| ConfigError(format!("{}: invalid {}: {}", source, key, e)))?;
+        self.sources.insert(key.to_string(), source);
+        Ok(())
+    }
+
+    /// 应用 PROCESSFORLINUX_* 环境变量
+    ///
+    /// 全局项把键名中的 . 换成 _ 并转为大写，例如 PROCESSFORLINUX_MEDIA_ENABLE；
+    /// 分类写作 PROCESSFORLINUX_CATEGORIES_<NAME>，目标项写作 PROCESSFORLINUX_TARGETS_<NAME>_<KEY>，
+    /// 请求头写作 PROCESSFORLINUX_TARGETS_<NAME>_HEADERS_<HEADER>（_ 会换成 -）
+    pub fn apply_env(&mut self, vars: impl Iterator<Item = (String, String)>) -> Result<(), ConfigError> {
+        let mut vars: Vec<(String, String)> = vars
+            .filter(|(var, _)| var.starts_with(ENV_PREFIX))
+            .collect();
+        // 保证新建目标的顺序稳定
+        vars.sort();
+
+        for (var, value) in vars {
+            // 拼错或过时的变量只警告，不影响启动和重新加载；认识的变量值不对时仍然报错
+            let Some(key) = self.env_key(&var[ENV_PREFIX.len()..]) else {
+                // 守护进程启动时日志还没有初始化，直接输出到标准错误
+                if tracing::dispatcher::has_been_set() {
+                    tracing::warn!("Ignoring unknown environment variable {}", var);
+                } else {
+                    eprintln!("Ignoring unknown environment variable {}", var);
+                }
+                continue;
+            };
+            self.set_from(&key, &value, Source::Env(var))?;
+        }
+        Ok(())
+    }
+
+    /// 把环境变量名（去掉前缀）映射为配置键名
+    fn env_key(&self, name: &str) -> Option<String> {
+        let env_name = |key: &str| key.to_uppercase().replace(['.', '-'], "_");
+        if let Some(key) = GLOBAL_KEYS.iter().find(|key| env_name(key) == name) {
+            return Some(key.to_string());
+        }
+        // 分类名同样优先匹配已有的分类，否则以小写名新建
+        if let Some(category) = name.strip_prefix("CATEGORIES_").filter(|category| !category.is_empty()) {
+            let name = self
+                .categories
+                .keys()
+                .find(|name| env_name(name) == category)
+                .map_or_else(|| category.to_lowercase(), |name| name.clone());
+            return Some(format!("categories.{}", name));
+        }
+
+        let rest = name.strip_prefix("TARGETS_")?;
+        let (target, field) = match rest.split_once("_HEADERS_") {
+            Some((target, header)) => (target, format!("headers.{}", header.to_lowercase().replace('_', "-"))),
+            None => {
+                // 取最长匹配的字段名，避免 AUTH_HEADER 被当成 HEADER
+                let field = TARGET_KEYS
+                    .iter()
+                    .filter(|key| rest.ends_with(&format!("_{}", env_name(key))))
+                    .max_by_key(|key| key.len())?;
+                (&rest[..rest.len() - field.len() - 1], field.to_string())
+            }
+        };
+        if target.is_empty() {
+            return None;
+        }
+        // 优先匹配已有的目标名，否则以小写名新建
+        let name = self
+            .targets
+            .iter()
+            .find(|t| env_name(&t.name) == target)
+            .map_or_else(|| target.to_lowercase(), |t| t.name.clone());
+        Some(format!("targets.{}.{}", name, field))
+    }
+
+    /// 把与默认值不同的项标记为来自配置文件
+    pub fn mark_file_sources(&mut self, origin: &str) {
+        // 时间表默认不存在，但出现时其中未写的项仍是默认值
+        let schedule_defaults = ScheduleConfig::default().entries();
+        let defaults: BTreeMap<String, String> = Config::default()
+            .entries()
+            .into_iter()
+            .chain(schedule_defaults.clone())
+            .collect();
+        let default_targets: BTreeMap<String, String> = self
+            .targets
+            .iter()
+            .flat_map(|target| {
+                let prefix = format!("targets.{}.", target.name);
+                TargetConfig::default()
+                    .entries()
+                    .into_iter()
+                    .chain(schedule_defaults.clone())
+                    .map(move |(key, value)| (format!("{}{}", prefix, key), value))
+            })
+            .collect();
+
+        for (key, value) in self.entries() {
+            let default = defaults.get(&key).or_else(|| default_targets.get(&key));
+            if default != Some(&value) {
+                self.sources.insert(key, Source::File(origin.to_string()));
+            }
+        }
+    }
+
+    /// 当前的所有配置项，键为完整路径
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let path = |path: &Option<PathBuf>| path.as_ref().map(|p| p.display().to_string());
+        let mut entries = vec![
+            ("watch_time".to_string(), self.watch_time.to_string()),
+            ("log_enable".to_string(), self.log_enable.to_string()),
+            ("log.level".to_string(), self.log.level.clone().unwrap_or_default()),
+            ("log.format".to_string(), self.log.format.clone()),
+            ("log.output".to_string(), self.log.output.clone()),
+            ("log.dir".to_string(), path(&self.log.dir).unwrap_or_default()),
+            ("log.rotation".to_string(), self.log.rotation.clone()),
+            ("log.max_size".to_string(), self.log.max_size.to_string()),
+            ("log.keep".to_string(), self.log.keep.to_string()),
+            ("media.enable".to_string(), self.media.enable.to_string()),
+            ("media.players".to_string(), self.media.players.join(",")),
+            ("gui.enable".to_string(), self.gui.enable.to_string()),
+            ("queue.enable".to_string(), self.queue.enable.to_string()),
+            ("queue.dir".to_string(), path(&self.queue.dir).unwrap_or_default()),
+            ("queue.max_entries".to_string(), self.queue.max_entries.to_string()),
+            ("queue.max_age".to_string(), self.queue.max_age.to_string()),
+            ("history.enable".to_string(), self.history.enable.to_string()),
+            ("history.dir".to_string(), path(&self.history.dir).unwrap_or_default()),
+            ("history.retention_days".to_string(), self.history.retention_days.to_string()),
+            ("history.titles".to_string(), self.history.titles.to_string()),
+            ("activitywatch.enable".to_string(), self.activitywatch.enable.to_string()),
+            ("activitywatch.url".to_string(), self.activitywatch.url.clone()),
+            (
+                "activitywatch.hostname".to_string(),
+                self.activitywatch.hostname.clone().unwrap_or_default(),
+            ),
+            (
+                "activitywatch.window_bucket".to_string(),
+                self.activitywatch.window_bucket.clone().unwrap_or_default(),
+            ),
+            (
+                "activitywatch.afk_bucket".to_string(),
+                self.activitywatch.afk_bucket.clone().unwrap_or_default(),
+            ),
+            ("activitywatch.afk_timeout".to_string(), self.activitywatch.afk_timeout.to_string()),
+            ("activitywatch.titles".to_string(), self.activitywatch.titles.to_string()),
+            ("http.enable".to_string(), self.http.enable.to_string()),
+            ("http.bind".to_string(), self.http.bind.clone()),
+            (
+                "http.token".to_string(),
+                self.http.token.as_deref().map(display_secret).unwrap_or_default(),
+            ),
+            ("http.allow_origin".to_string(), self.http.allow_origin.clone().unwrap_or_default()),
+            ("extend.language".to_string(), self.extend.language.clone().unwrap_or_default()),
+            ("extend.builtin".to_string(), self.extend.builtin.to_string()),
+            (
+                "extend.texts".to_string(),
+                self.extend.texts.iter().map(|text| text.to_string()).collect::<Vec<_>>().join("; "),
+            ),
+            ("rules.exclude".to_string(), self.rules.exclude.join(",")),
+            ("rules.allow".to_string(), join_rules(&self.rules.allow)),
+            ("rules.deny".to_string(), join_rules(&self.rules.deny)),
+            ("rules.unlisted".to_string(), self.rules.unlisted.clone()),
+            ("rules.unlisted_label".to_string(), self.rules.unlisted_label.clone()),
+            ("rules.busy_label".to_string(), self.rules.busy_label.clone()),
+            ("privacy.mode".to_string(), self.privacy.mode.clone()),
+            ("privacy.process".to_string(), self.privacy.process.clone()),
+            ("privacy.extend".to_string(), self.privacy.extend.clone()),
+            (
+                "privacy.pause_minutes".to_string(),
+                self.privacy.pause_minutes.map(|m| m.to_string()).unwrap_or_default(),
+            ),
+            ("shutdown.report".to_string(), self.shutdown.report.to_string()),
+            ("shutdown.process".to_string(), self.shutdown.process.clone()),
+            ("shutdown.extend".to_string(), self.shutdown.extend.clone()),
+            ("shutdown.timeout".to_string(), self.shutdown.timeout.to_string()),
+        ];
+        for (name, apps) in &self.categories {
+            entries.push((format!("categories.{}", name), apps.join(",")));
+        }
+        if let Some(schedule) = &self.schedule {
+            entries.extend(schedule.entries());
+        }
+        for target in &self.targets {
+            for (key, value) in target.entries() {
+                entries.push((format!("targets.{}.{}", target.name, key), value));
+            }
+        }
+        entries
+    }
+
+    pub fn source_of(&self, key: &str) -> &Source {
+        self.sources.get(key).unwrap_or(&Source::Default)
+    }
+
+    /// 校验整个配置；source 为 TOML 原文时，错误信息会带上行列号
+    pub fn finalize(&mut self, origin: &str, source: Option<&str>) -> Result<(), ConfigError> {
+        // 命令行和环境变量设置的项报告来源，配置文件中的项报告行列号
+        let sources = self.sources.clone();
+        let located = |path: &[&str], message: String| {
+            if let Some(source @ (Source::Env(_) | Source::Cli(_))) = sources.get(&path.join(".")) {
+                return ConfigError(format!("{}: {}", source, message));
+            }
+            match source.and_then(|source| locate(source, path)) {
+                Some((line, column)) => ConfigError(format!("{}:{}:{}: {}", origin, line, column, message)),
+                None => ConfigError(format!("{}: {}", origin, message)),
+            }
+        };
+
+        if self.watch_time < 1 {
+            return Err(located(&["watch_time"], "'watch_time' must be at least 1 second".to_string()));
+        }
+        self.log.format = self.log.format.to_lowercase();
+        if !LOG_FORMATS.contains(&self.log.format.as_str()) {
+            return Err(located(
+                &["log", "format"],
+                format!(
+                    "unknown log format '{}', expected one of: {}",
+                    self.log.format,
+                    LOG_FORMATS.join(", ")
+                ),
+            ));
+        }
+        self.log.output = self.log.output.to_lowercase();
+        if !LOG_OUTPUTS.contains(&self.log.output.as_str()) {
+            return Err(located(
+                &["log", "output"],
+                format!(
+                    "unknown log output '{}', expected one of: {}",
+                    self.log.output,
+                    LOG_OUTPUTS.join(", ")
+                ),
+            ));
+        }
+        self.log.rotation = self.log.rotation.to_lowercase();
+        if !LOG_ROTATIONS.contains(&self.log.rotation.as_str()) {
+            return Err(located(
+                &["log", "rotation"],
+                format!(
+                    "unknown log rotation '{}', expected one of: {}",
+                    self.log.rotation,
+                    LOG_ROTATIONS.join(", ")
+                ),
+            ));
+        }
+        if let Err(e) = EnvFilter::try_new(self.log_level()) {
+            return Err(located(&["log", "level"], format!("log: invalid level '{}': {}", self.log_level(), e)));
+        }
+        self.privacy.mode = self.privacy.mode.to_lowercase();
+        if !PRIVACY_MODES.contains(&self.privacy.mode.as_str()) {
+            return Err(located(
+                &["privacy", "mode"],
+                format!(
+                    "unknown privacy mode '{}', expected one of: {}",
+                    self.privacy.mode,
+                    PRIVACY_MODES.join(", ")
+                ),
+            ));
+        }
+        if let Err(e) = pause::resume_time(chrono::Utc::now(), self.privacy.pause_minutes) {
+            return Err(located(&["privacy", "pause_minutes"], format!("privacy: {}", e)));
+        }
+        if self.shutdown.timeout < 1 {
+            return Err(located(&["shutdown", "timeout"], "'shutdown.timeout' must be at least 1 second".to_string()));
+        }
+        let global_schedule = match &self.schedule {
+            Some(schedule) => Some(
+                Schedule::from_config(schedule)
+                    .map_err(|message| located(&["schedule"], format!("schedule: {}", message)))?,
+            ),
+            None => None,
+        };
+        ExtendTexts::compile(&self.extend)
+            .map_err(|message| located(&["extend"], format!("extend: {}", message)))?;
+        self.rules.unlisted = self.rules.unlisted.to_lowercase();
+        AppRules::compile(&self.rules, &[], &[])
+            .map_err(|message| located(&["rules"], format!("rules: {}", message)))?;
+        if self.activitywatch.enable && !self.activitywatch.url.starts_with("http") {
+            return Err(located(
+                &["activitywatch", "url"],
+                format!("activitywatch: invalid url '{}'", self.activitywatch.url),
+            ));
+        }
+        if self.http.enable {
+            let address: SocketAddr = self.http.bind.parse().map_err(|e| {
+                located(&["http", "bind"], format!("http: invalid bind address '{}': {}", self.http.bind, e))
+            })?;
+            if let Some(reference) = &self.http.token {
+                self.http.resolved_token = secret_store::resolve(reference)
+                    .map_err(|e| located(&["http", "token"], format!("http: {}", e)))?;
+            }
+            // 监听其他地址时局域网内都能访问，必须设置 token
+            if !address.ip().is_loopback() && self.http.resolved_token.expose().is_empty() {
+                return Err(located(
+                    &["http", "bind"],
+                    format!("http: a token is required when binding to {}", address),
+                ));
+            }
+        }
+        // 只记录本地历史、只发给 ActivityWatch 或只提供 HTTP 接口时可以不配置上报目标
+        if self.targets.is_empty() && !self.history.enable && !self.activitywatch.enable && !self.http.enable {
+            return Err(ConfigError(format!(
+                "{}: no report target configured, add at least one [[targets]] section or enable [history]",
+                origin
+            )));
+        }
+
+        for index in 0..self.targets.len() {
+            let duplicate = self.targets[..index]
+                .iter()
+                .any(|other| other.name == self.targets[index].name);
+            let target = &mut self.targets[index];
+            if duplicate {
+                return Err(located(
+                    &["targets", &target.name],
+                    format!("duplicate target name '{}'", target.name),
+                ));
+            }
+            target.resolved_schedule = match &target.schedule {
+                Some(schedule) => Some(
+                    Schedule::from_config(schedule)
+                        .map_err(|message| format!("target '{}': schedule: {}", target.name, message))
+                        .map_err(|message| located(&["targets", &target.name, "schedule"], message))?,
+                ),
+                None => global_schedule.clone(),
+            };
+            target.resolved_rules = AppRules::compile(&self.rules, &target.allow, &target.deny)
+                .map_err(|message| format!("target '{}': {}", target.name, message))
+                .map_err(|message| located(&["targets", &target.name], message))?;
+            if let Err(message) = target.finalize() {
+                let message = if target.name.is_empty() {
+                    message
+                } else {
+                    format!("target '{}': {}", target.name, message)
+                };
+                return Err(located(&["targets", &target.name], message));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// 在 TOML 原文中查找配置项的位置，返回 (行号, 列号)，均从 1 开始
+///
+/// path 为表名和键名，例如 ["privacy", "mode"]；[[targets]] 中按 name 查找，同名时取最后一个，
+/// 重复的目标名报告在后出现的那个上。原文中没有这个键时退回到所在的表
+fn locate(source: &str, path: &[&str]) -> Option<(usize, usize)> {
+    let document = toml_edit::ImDocument::parse(source).ok()?;
+    let mut table: &dyn TableLike = document.as_table();
+    let mut span = None;
+    let mut segments = path.iter();
+    while let Some(segment) = segments.next() {
+        let Some((key, item)) = table.get_key_value(segment) else {
+            break;
+        };
+        if let Some(tables) = item.as_array_of_tables() {
+            // 目标名不在原文中时（环境变量新建的目标）没有位置
+            let name = segments.next()?;
+            let target = tables
+                .iter()
+                .filter(|target| target.get("name").and_then(|name| name.as_str()) == Some(*name))
+                .last()?;
+            span = target.span();
+            table = target;
+            continue;
+        }
+        // 表指向表头的 [，其余指向键
+        span = item.as_table().and_then(|table| table.span()).or(key.span());
+        match item.as_table_like() {
+            Some(inner) => table = inner,
+            None => break,
+        }
+    }
+
+    let offset = span?.start;
+    let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+    let line = source[..offset].matches('\n').count() + 1;
+    Some((line, source[line_start..offset].chars().count() + 1))
+}
+
+/// 默认配置文件位置：$XDG_CONFIG_HOME/processforlinux/config.toml
+pub fn default_config_path() -> PathBuf {
+    app_dirs::config_dir().join("config.toml")
+}
+
+/// 读取 TOML 配置，返回配置和原文（用于定位校验错误）；校验在应用覆盖项之后进行
+pub fn read_toml(path: &Path) -> Result<(Config, String), Box<dyn Error>> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|e| ConfigError(format!("Failed to read {}: {}", path.display(), e)))?;
+
+    // toml 的解析错误本身带有行列号和出错位置的片段
+    let config: Config = toml::from_str(&source)
+        .map_err(|e| ConfigError(format!("{}: {}", path.display(), e)))?;
+    Ok((config, source))
+}
+
+fn parse_value<T: FromStr>(value: &str) -> Result<T, String>
+where
+    T::Err: Display,
+{
+    value
+        .trim()
+        .parse()
+        .map_err(|e| format!("'{}' ({})", value, e))
+}
+
+/// 逗号分隔的列表
+fn parse_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// 用于展示的密钥：keyring: / file: / env: 引用原样显示，明文密钥遮盖
+fn display_secret(value: &str) -> String {
+    let is_reference = ["keyring:", "file:", "env:"]
+        .iter()
+        .any(|prefix| value.starts_with(prefix));
+    if is_reference {
+        value.to_string()
+    } else {
+        Secret::new(value).masked().to_string()
+    }
+}
+
+/// 空值表示取消设置
+fn optional(value: &str) -> Option<String> {
+    Some(value.trim().to_string()).filter(|value| !value.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"watch_time = 5
+
+[schedule]
+mode = "silent"
+
+[[targets]]
+name = "site"
+type = "webhook"
+url = "https://example.com/hook"
+
+[activitywatch]
+enable = true
+url = "localhost:5600"
+
+[privacy]
+mode = "bogus"
+
+[[targets]]
+name = "copy"
+type = "stdout"
+"#;
+
+    fn finalize(source: &str) -> Result<Config, ConfigError> {
+        let mut config: Config = toml::from_str(source).unwrap();
+        config.mark_file_sources("config.toml");
+        config.finalize("config.toml", Some(source)).map(|_| config)
+    }
+
+    #[test]
+    fn locates_keys_in_their_own_table() {
+        assert_eq!(locate(SOURCE, &["watch_time"]), Some((1, 1)));
+        assert_eq!(locate(SOURCE, &["schedule"]), Some((3, 1)));
+        assert_eq!(locate(SOURCE, &["schedule", "mode"]), Some((4, 1)));
+        assert_eq!(locate(SOURCE, &["activitywatch", "url"]), Some((13, 1)));
+        assert_eq!(locate(SOURCE, &["privacy", "mode"]), Some((16, 1)));
+    }
+
+    #[test]
+    fn locates_targets_by_name() {
+        assert_eq!(locate(SOURCE, &["targets", "site", "url"]), Some((9, 1)));
+        assert_eq!(locate(SOURCE, &["targets", "copy"]), Some((18, 1)));
+        // 环境变量新建的目标不在原文中
+        assert_eq!(locate(SOURCE, &["targets", "env-only"]), None);
+        // 没写的键退回到目标本身
+        assert_eq!(locate(SOURCE, &["targets", "copy", "schedule"]), Some((18, 1)));
+    }
+
+    #[test]
+    fn locates_dotted_and_indented_keys() {
+        let source = "privacy.mode = \"bogus\"\n[log]\n  format = \"xml\"\n";
+        assert_eq!(locate(source, &["privacy", "mode"]), Some((1, 9)));
+        assert_eq!(locate(source, &["log", "format"]), Some((3, 3)));
+        assert_eq!(locate(source, &["log", "output"]), Some((2, 1)));
+    }
+
+    #[test]
+    fn reports_the_failing_key() {
+        // 前面 [schedule] 中的 mode 和目标中的 url 不会被误认
+        let error = finalize(SOURCE).unwrap_err().0;
+        assert!(error.starts_with("config.toml:16:1: unknown privacy mode 'bogus'"), "{}", error);
+
+        let source = SOURCE.replace("\"bogus\"", "\"silent\"");
+        let error = finalize(&source).unwrap_err().0;
+        assert!(error.starts_with("config.toml:13:1: activitywatch: invalid url"), "{}", error);
+    }
+
+    #[test]
+    fn reports_duplicate_target_at_second_definition() {
+        let source = SOURCE
+            .replace("localhost:5600", "http://localhost:5600")
+            .replace("\"bogus\"", "\"silent\"")
+            .replace("name = \"copy\"", "name = \"site\"");
+        let error = finalize(&source).unwrap_err().0;
+        assert_eq!(error, "config.toml:18:1: duplicate target name 'site'");
+    }
+
+    #[test]
+    fn every_global_key_can_be_set() {
+        let entries: BTreeMap<String, String> = Config::default().entries().into_iter().collect();
+        let mut config = Config::default();
+        for key in GLOBAL_KEYS.iter().filter(|key| !key.starts_with("schedule.")) {
+            let value = entries.get(*key).unwrap_or_else(|| panic!("{} is missing from entries", key));
+            config.set(key, value).unwrap_or_else(|e| panic!("{}: {}", key, e));
+        }
+        let mut config = Config::default();
+        for key in GLOBAL_KEYS.iter().filter(|key| key.starts_with("schedule.")) {
+            config.set(key, "").unwrap_or_else(|e| panic!("{}: {}", key, e));
+        }
+    }
+
+    #[test]
+    fn maps_env_names_to_keys() {
+        let mut config = Config::default();
+        config.categories.insert("Work".to_string(), vec!["Code".to_string()]);
+        let key = |name: &str| config.env_key(name);
+        assert_eq!(key("LOG_MAX_SIZE").as_deref(), Some("log.max_size"));
+        assert_eq!(key("SCHEDULE_QUIET").as_deref(), Some("schedule.quiet"));
+        assert_eq!(key("CATEGORIES_WORK").as_deref(), Some("categories.Work"));
+        assert_eq!(key("CATEGORIES_GAMES").as_deref(), Some("categories.games"));
+        assert_eq!(key("CATEGORIES_"), None);
+        assert_eq!(key("TARGETS_BLOG_AUTH_HEADER").as_deref(), Some("targets.blog.auth_header"));
+
+        config
+            .apply_env([("PROCESSFORLINUX_CATEGORIES_GAMES".to_string(), "Steam, Lutris".to_string())].into_iter())
+            .unwrap();
+        assert_eq!(config.categories["games"], ["Steam", "Lutris"]);
+    }
+
+    #[test]
+    fn reports_env_source_instead_of_file_position() {
+        let mut config: Config = toml::from_str(SOURCE).unwrap();
+        config.mark_file_sources("config.toml");
+        config
+            .apply_env([("PROCESSFORLINUX_PRIVACY_MODE".to_string(), "other".to_string())].into_iter())
+            .unwrap();
+        let error = config.finalize("config.toml", Some(SOURCE)).unwrap_err().0;
+        assert!(error.starts_with("env PROCESSFORLINUX_PRIVACY_MODE: unknown privacy mode"), "{}", error);
+    }
+
+    #[test]
+    fn skips_unknown_env_vars() {
+        let mut config = Config::default();
+        config
+            .apply_env(
+                [
+                    ("PROCESSFORLINUX_WATCH_TIEM".to_string(), "10".to_string()),
+                    ("PROCESSFORLINUX_WATCH_TIME".to_string(), "10".to_string()),
+                ]
+                .into_iter(),
+            )
+            .unwrap();
+        assert_eq!(config.watch_time, 10);
+
+        // 认识的变量值不对时仍然报错
+        let error = config
+            .apply_env([("PROCESSFORLINUX_WATCH_TIME".to_string(), "soon".to_string())].into_iter())
+            .unwrap_err()
+            .0;
+        assert!(error.contains("PROCESSFORLINUX_WATCH_TIME"), "{}", error);
+    }
+}
@@ -0,0 +1,145 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/config_watcher.rs
+ */
+
+use crate::config::Config;
+use crate::get_env_file;
+use clap::ArgMatches;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+/// 文件变化后等待一小段时间再读取，编辑器保存时往往会触发多次事件
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// 应用结果的回复通道，用于控制套接字的 reload 请求
+pub type ReloadReply = oneshot::Sender<Result<(), String>>;
+
+/// 一次重新加载：校验通过的新配置或错误信息；由 reload 请求触发时附带回复通道
+pub struct Reload {
+    pub result: Result<Config, String>,
+    pub reply: Option<ReloadReply>,
+}
+
+pub struct ConfigWatcher {
+    reloads: mpsc::UnboundedReceiver<Reload>,
+    trigger: mpsc::UnboundedSender<ReloadReply>,
+}
+
+impl ConfigWatcher {
+    /// 等待下一次重新加载
+    pub async fn next(&mut self) -> Option<Reload> {
+        self.reloads.recv().await
+    }
+
+    /// 手动触发一次重新加载，应用后通过 reply 回复结果
+    pub fn reload(&self, reply: ReloadReply) {
+        if let Err(mpsc::error::SendError(reply)) = self.trigger.send(reply) {
+            let _ = reply.send(Err("config watcher has stopped".to_string()));
+        }
+    }
+}
+
+/// 监听配置文件变化（inotify）和 SIGHUP，每次触发都重新读取并校验配置
+///
+/// 必须在 tokio 运行时中调用
+pub fn watch(matches: ArgMatches) -> ConfigWatcher {
+    let (reload_sender, reload_receiver) = mpsc::unbounded_channel();
+    let (trigger, mut trigger_receiver) = mpsc::unbounded_channel();
+    let (event_sender, mut event_receiver) = mpsc::unbounded_channel();
+
+    // 监听配置文件所在目录而不是文件本身：很多编辑器保存时会用新文件替换旧文件
+    let watcher = match get_env_file::config_path(&matches) {
+        Ok(Some(path)) => {
+            let file_name = path.file_name().map(|name| name.to_os_string());
+            let directory = path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default();
+            let result = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let Ok(event) = event else { return };
+                let relevant = matches!(
+                    event.kind,
+                    EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                ) && event
+                    .paths
+                    .iter()
+                    .any(|changed| changed.file_name().map(|name| name.to_os_string()) == file_name);
+                if relevant {
+                    let _ = event_sender.send(());
+                }
+            })
+            .and_then(|mut watcher| {
+                watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+                Ok(watcher)
+            });
+            match result {
+                Ok(watcher) => {
+                    info!("Watching {} for changes", path.display());
+                    Some(watcher)
+                }
+                Err(e) => {
+                    warn!("Failed to watch {}: {}, reload with SIGHUP instead", path.display(), e);
+                    None
+                }
+            }
+        }
+        // 没有配置文件时只能通过 SIGHUP 重新读取环境变量之外的设置
+        _ => None,
+    };
+
+    tokio::spawn(async move {
+        // watcher 被丢弃时会停止监听，所以要让它和任务活得一样久
+        let _watcher = watcher;
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => Some(hangup),
+            Err(e) => {
+                warn!("Failed to listen for SIGHUP: {}", e);
+                None
+            }
+        };
+
+        loop {
+            let reply = tokio::select! {
+                Some(()) = event_receiver.recv() => {
+                    tokio::time::sleep(DEBOUNCE).await;
+                    while event_receiver.try_recv().is_ok() {}
+                    info!("Config file changed, reloading");
+                    None
+                }
+                Some(()) = async {
+                    match hangup.as_mut() {
+                        Some(hangup) => hangup.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    info!("Received SIGHUP, reloading config");
+                    None
+                }
+                Some(reply) = trigger_receiver.recv() => {
+                    info!("Reload requested, reloading config");
+                    Some(reply)
+                }
+                else => break,
+            };
+
+            // 读取密钥可能会访问 D-Bus，放到阻塞线程里
+            let matches = matches.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                get_env_file::get_env_file(&matches).map_err(|e| e.to_string())
+            })
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+
+            if reload_sender.send(Reload { result, reply }).is_err() {
+                break;
+            }
+        }
+    });
+
+    ConfigWatcher {
+        reloads: reload_receiver,
+        trigger,
+    }
+}
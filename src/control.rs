@@ -0,0 +1,275 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/control.rs
+ */
+
+use crate::app_dirs;
+use crate::status_window::{AppStatus, TargetHealth};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::UnixListener;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::warn;
+
+/// 控制套接字上的请求，每行一个 JSON，例如 {"command":"status"}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Status,
+    /// 暂停上报，minutes 为空时一直暂停到 resume
+    Pause {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        minutes: Option<u64>,
+    },
+    Resume,
+    /// 立即检测并上报一次
+    Report,
+    /// 重新读取配置文件
+    Reload,
+    /// 显示被隐藏的悬浮窗，再次启动程序时发送
+    ShowOverlay,
+    /// 订阅事件流，回复之后每行一个事件，直到断开连接
+    Subscribe,
+}
+
+/// 事件流中的事件，例如 {"event":"paused","until":null}
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// 每次检测后的状态
+    Status { status: Box<AppStatus> },
+    /// 一次上报及上报后各目标的状态；activity 经过全局规则过滤，被丢弃时为 null
+    Report { activity: Value, targets: Vec<TargetHealth> },
+    /// 前台窗口变化，已经过全局规则过滤；暂停期间和被规则丢弃时不发送
+    Focus { process: String, class: String, title: String },
+    /// 正在播放的媒体变化，停止播放时 title 为空；暂停期间和被规则丢弃时不发送
+    Media { title: String, artist: String },
+    Paused { until: Option<DateTime<Utc>> },
+    Resumed,
+    ConfigReloaded,
+    ConfigError { error: String },
+    /// 正在退出，之后会发送离线状态并断开连接
+    Stopping,
+}
+
+/// 需要 run_loop 处理的请求，处理完后通过 reply 回复
+pub struct ControlMessage {
+    pub request: Request,
+    pub reply: oneshot::Sender<Result<(), String>>,
+}
+
+pub type ControlSender = mpsc::UnboundedSender<ControlMessage>;
+pub type ControlReceiver = mpsc::UnboundedReceiver<ControlMessage>;
+
+/// 不需要回复的请求，例如悬浮窗上的按钮
+pub fn send_detached(sender: &ControlSender, request: Request) {
+    let (reply, _) = oneshot::channel();
+    let _ = sender.send(ControlMessage { request, reply });
+}
+
+/// 控制套接字位置：$XDG_RUNTIME_DIR/processforlinux/control.sock
+pub fn socket_path() -> PathBuf {
+    app_dirs::runtime_dir().join("control.sock")
+}
+
+/// 启动控制套接字，status 直接读取最新的状态，subscribe 订阅 events，其他请求交给 run_loop
+///
+/// 必须在 tokio 运行时中调用
+pub fn serve(
+    status: Arc<Mutex<AppStatus>>,
+    commands: ControlSender,
+    events: broadcast::Sender<Event>,
+) -> Result<PathBuf, Box<dyn Error>> {
+    let path = socket_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if path.exists() {
+        // 能连上说明已经有实例在运行，否则是上次异常退出留下的文件
+        if UnixStream::connect(&path).is_ok() {
+            return Err(format!("{} is already in use by another instance", path.display()).into());
+        }
+        std::fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+    // 只允许当前用户访问
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+
+    tokio::spawn(async move {
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Control socket accept failed: {}", e);
+                    continue;
+                }
+            };
+            let status = status.clone();
+            let commands = commands.clone();
+            let events = events.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, status, commands, events).await {
+                    warn!("Control connection failed: {}", e);
+                }
+            });
+        }
+    });
+
+    Ok(path)
+}
+
+async fn handle_connection(
+    stream: tokio::net::UnixStream,
+    status: Arc<Mutex<AppStatus>>,
+    commands: ControlSender,
+    events: broadcast::Sender<Event>,
+) -> Result<(), Box<dyn Error>> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = tokio::io::BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(Request::Status) => {
+                let status = status.lock().unwrap().clone();
+                json!({ "ok": true, "status": status })
+            }
+            Ok(Request::Subscribe) => {
+                let mut receiver = events.subscribe();
+                writer.write_all(b"{\"ok\":true}\n").await?;
+                loop {
+                    match receiver.recv().await {
+                        Ok(event) => {
+                            let line = format!("{}\n", serde_json::to_string(&event)?);
+                            // 客户端断开时写入失败，结束订阅
+                            writer.write_all(line.as_bytes()).await?;
+                        }
+                        // 客户端太慢，跳过错过的事件
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return Ok(()),
+                    }
+                }
+            }
+            Ok(request) => {
+                let (reply, reply_receiver) = oneshot::channel();
+                let result = match commands.send(ControlMessage { request, reply }) {
+                    Ok(()) => reply_receiver
+                        .await
+                        .unwrap_or_else(|_| Err("daemon is shutting down".to_string())),
+                    Err(_) => Err("daemon is shutting down".to_string()),
+                };
+                match result {
+                    Ok(()) => json!({ "ok": true }),
+                    Err(e) => json!({ "ok": false, "error": e }),
+                }
+            }
+            Err(e) => json!({ "ok": false, "error": format!("invalid request: {}", e) }),
+        };
+        writer.write_all(format!("{}\n", response).as_bytes()).await?;
+    }
+    Ok(())
+}
+
+fn connect() -> Result<UnixStream, Box<dyn Error>> {
+    let path = socket_path();
+    UnixStream::connect(&path).map_err(|e| {
+        format!(
+            "processforlinux does not seem to be running ({}: {}), start it with `processforlinux run`",
+            path.display(),
+            e
+        )
+        .into()
+    })
+}
+
+/// 向正在运行的实例发送一个请求并等待回复
+pub fn send(request: &Request) -> Result<Value, Box<dyn Error>> {
+    let mut stream = connect()?;
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(30)))?;
+    stream.write_all(format!("{}\n", serde_json::to_string(request)?).as_bytes())?;
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    let response: Value = serde_json::from_str(&line)
+        .map_err(|e| format!("Invalid response from daemon: {}", e))?;
+    if response["ok"].as_bool() != Some(true) {
+        let error = response["error"].as_str().unwrap_or("unknown error");
+        return Err(error.to_string().into());
+    }
+    Ok(response)
+}
+
+/// events 子命令：订阅事件流，每行打印一个 JSON 事件
+pub fn print_events() -> Result<(), Box<dyn Error>> {
+    let mut stream = connect()?;
+    stream.write_all(format!("{}\n", serde_json::to_string(&Request::Subscribe)?).as_bytes())?;
+
+    let mut lines = BufReader::new(&stream).lines();
+    // 第一行是订阅的回复
+    lines.next().transpose()?;
+    for line in lines {
+        println!("{}", line?);
+    }
+    Ok(())
+}
+
+/// status 子命令
+pub fn print_status(format: &str) -> Result<(), Box<dyn Error>> {
+    let response = send(&Request::Status)?;
+    let status = &response["status"];
+    if format == "json" {
+        println!("{}", serde_json::to_string_pretty(status)?);
+        return Ok(());
+    }
+
+    let text = |value: &Value| value.as_str().unwrap_or_default().to_string();
+    println!("Session:    {}", text(&status["session_type"]));
+    println!("Window:     {}", text(&status["current_window"]));
+    if let Some(error) = status["window_status"]["error"].as_str() {
+        println!("            ({})", error);
+    }
+    let title = text(&status["media_title"]);
+    if !title.is_empty() {
+        println!("Media:      {} - {}", title, text(&status["media_artist"]));
+    }
+    let reporting = match (status["paused"].as_bool(), status["paused_until"].as_str()) {
+        (Some(true), Some(until)) => format!("paused until {}", until),
+        (Some(true), None) => "paused".to_string(),
+        _ => "active".to_string(),
+    };
+    println!("Reporting:  {}", reporting);
+    println!("Interval:   {}s", status["watch_interval"]);
+    if let Some(error) = status["config_error"].as_str() {
+        println!("Config:     reload failed, using previous config: {}", error);
+    }
+
+    if let Some(targets) = status["targets"].as_array() {
+        println!("Targets:");
+        for target in targets {
+            print!(
+                "  {:<16} ok {:<6} failed {:<6} queued {}",
+                text(&target["name"]),
+                target["success_count"],
+                target["failure_count"],
+                target["queued"]
+            );
+            match target["last_error"].as_str() {
+                Some(error) => println!("  ({})", error),
+                None => println!(),
+            }
+        }
+    }
+    Ok(())
+}
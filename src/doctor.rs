@@ -0,0 +1,325 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/doctor.rs
+ */
+
+use crate::config::Config;
+use crate::control;
+use crate::get_active_window;
+use crate::get_env_file;
+use clap::ArgMatches;
+use dbus::blocking::Connection;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+const MPRIS_PREFIX: &str = "org.mpris.MediaPlayer2.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Ok,
+    /// 没有检查，既不算通过也不算失败
+    Skip,
+    Warn,
+    Fail,
+}
+
+/// 一项检查的结果，hint 是给用户的修复建议
+struct Check {
+    level: Level,
+    name: &'static str,
+    detail: String,
+    hint: Option<String>,
+}
+
+impl Check {
+    fn ok(name: &'static str, detail: impl Into<String>) -> Self {
+        Check { level: Level::Ok, name, detail: detail.into(), hint: None }
+    }
+
+    fn skip(name: &'static str, detail: impl Into<String>) -> Self {
+        Check { level: Level::Skip, name, detail: detail.into(), hint: None }
+    }
+
+    fn warn(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check { level: Level::Warn, name, detail: detail.into(), hint: Some(hint.into()) }
+    }
+
+    fn fail(name: &'static str, detail: impl Into<String>, hint: impl Into<String>) -> Self {
+        Check { level: Level::Fail, name, detail: detail.into(), hint: Some(hint.into()) }
+    }
+
+    fn print(&self) {
+        let mark = match self.level {
+            Level::Ok => "✓",
+            Level::Skip => "-",
+            Level::Warn => "!",
+            Level::Fail => "✗",
+        };
+        println!("{} {:<10} {}", mark, self.name, self.detail);
+        if let Some(hint) = &self.hint {
+            println!("  → {}", hint);
+        }
+    }
+}
+
+fn has_command(program: &str, arg: &str) -> bool {
+    Command::new(program).arg(arg).output().is_ok()
+}
+
+fn check_session() -> Vec<Check> {
+    let session_type = get_active_window::detect_session_type();
+    let mut checks = Vec::new();
+
+    match session_type.as_str() {
+        "x11" => {
+            checks.push(Check::ok("session", "x11"));
+            if has_command("xprop", "-version") {
+                checks.push(Check::ok("backend", "xprop"));
+            } else {
+                checks.push(Check::fail(
+                    "backend",
+                    "xprop not found",
+                    "install xprop (x11-utils on Debian/Ubuntu, xorg-xprop on Arch)",
+                ));
+            }
+        }
+        "wayland" if get_active_window::is_kde_session() => {
+            checks.push(Check::ok("session", "wayland (KDE Plasma)"));
+            match get_active_window::detect_dbus_tool() {
+                Some(tool) => checks.push(Check::ok("backend", format!("KWin script via {}", tool))),
+                None => checks.push(Check::fail(
+                    "backend",
+                    "qdbus6/qdbus not found",
+                    "install the Qt D-Bus tools (qt6-tools or qdbus-qt6)",
+                )),
+            }
+            if !has_command("journalctl", "--version") {
+                checks.push(Check::fail(
+                    "backend",
+                    "journalctl not found",
+                    "the KWin backend reads script output from the systemd journal",
+                ));
+            }
+        }
+        "wayland" => checks.push(Check::fail(
+            "session",
+            "wayland (not KDE Plasma)",
+            "only KDE Plasma is supported on Wayland, log in to an X11 session instead",
+        )),
+        other => checks.push(Check::warn(
+            "session",
+            format!("unknown ({})", other),
+            "run inside a graphical session or set XDG_SESSION_TYPE / DISPLAY",
+        )),
+    }
+
+    match get_active_window::get_active_window_info() {
+        Ok(window) if !window.process.is_empty() || !window.class.is_empty() => {
+            // 未知应用没有应用名，显示窗口类名
+            let name = if window.process.is_empty() { &window.class } else { &window.process };
+            let detail = if window.title.is_empty() {
+                name.clone()
+            } else {
+                format!("{} ({})", name, window.title)
+            };
+            checks.push(Check::ok("window", detail))
+        }
+        Ok(_) => checks.push(Check::warn(
+            "window",
+            "no active window detected",
+            "focus a window and try again; if this persists the backend may not work on this desktop",
+        )),
+        Err(e) => checks.push(Check::fail("window", e.to_string(), "see the backend check above")),
+    }
+    checks
+}
+
+fn check_media(config: Option<&Config>) -> Vec<Check> {
+    let connection = match Connection::new_session() {
+        Ok(connection) => connection,
+        Err(e) => {
+            return vec![Check::fail(
+                "dbus",
+                e.to_string(),
+                "make sure DBUS_SESSION_BUS_ADDRESS is set (run from your desktop session)",
+            )]
+        }
+    };
+    let mut checks = vec![Check::ok("dbus", "session bus connected")];
+
+    if config.is_some_and(|config| !config.media.enable) {
+        checks.push(Check::ok("media", "disabled in config"));
+        return checks;
+    }
+
+    let proxy = connection.with_proxy(
+        "org.freedesktop.DBus",
+        "/org/freedesktop/DBus",
+        Duration::from_millis(5000),
+    );
+    let names: Result<(Vec<String>,), dbus::Error> =
+        proxy.method_call("org.freedesktop.DBus", "ListNames", ());
+    let running: Vec<String> = match names {
+        Ok((names,)) => names.into_iter().filter(|name| name.starts_with(MPRIS_PREFIX)).collect(),
+        Err(e) => {
+            checks.push(Check::fail("media", e.to_string(), "the session bus did not answer ListNames"));
+            return checks;
+        }
+    };
+
+    let players = config
+        .map(|config| config.media.players.clone())
+        .unwrap_or_else(|| Config::default().media.players);
+    let configured: Vec<&String> = running.iter().filter(|name| players.contains(name)).collect();
+
+    if !configured.is_empty() {
+        let names: Vec<&str> = configured.iter().map(|name| name.as_str()).collect();
+        checks.push(Check::ok("media", names.join(", ")));
+    } else if !running.is_empty() {
+        checks.push(Check::warn(
+            "media",
+            format!("running players are not configured: {}", running.join(", ")),
+            format!("add them to [media] players, e.g. players = [\"{}\"]", running[0]),
+        ));
+    } else {
+        checks.push(Check::warn(
+            "media",
+            "no MPRIS player running",
+            "start a player that supports MPRIS to report media",
+        ));
+    }
+    checks
+}
+
+async fn check_targets(config: &Config) -> Vec<Check> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(5))
+        .build()
+        .unwrap_or_default();
+    let mut checks = Vec::new();
+
+    for target in &config.targets {
+        let check = match target.kind.as_str() {
+            "mixspace" | "webhook" => {
+                let url = target.url.clone().unwrap_or_default();
+                // 只检查能否连上，任何 HTTP 响应都算可达
+                match client.head(&url).send().await {
+                    Ok(response) => Check::ok(
+                        "target",
+                        format!("{}: {} reachable (HTTP {})", target.name, url, response.status().as_u16()),
+                    ),
+                    Err(e) => Check::fail(
+                        "target",
+                        format!("{}: {} unreachable: {}", target.name, url, e),
+                        "check the url and your network connection",
+                    ),
+                }
+            }
+            "unix" => {
+                let path = target.path.clone().unwrap_or_default();
+                match std::os::unix::net::UnixStream::connect(&path) {
+                    Ok(_) => Check::ok("target", format!("{}: {} accepting", target.name, path.display())),
+                    Err(e) => Check::warn(
+                        "target",
+                        format!("{}: {}: {}", target.name, path.display(), e),
+                        "start the program listening on this socket",
+                    ),
+                }
+            }
+            "jsonl" => {
+                let path = target.jsonl_path();
+                match check_writable(&path) {
+                    Ok(()) => Check::ok("target", format!("{}: {} writable", target.name, path.display())),
+                    Err(e) => Check::fail(
+                        "target",
+                        format!("{}: {}: {}", target.name, path.display(), e),
+                        "check the path and the permissions of its directory",
+                    ),
+                }
+            }
+            kind => Check::skip("target", format!("{}: {} not checked", target.name, kind)),
+        };
+        checks.push(check);
+    }
+
+    if config.activitywatch.enable {
+        let url = format!("{}/api/0/info", config.activitywatch.url.trim_end_matches('/'));
+        let check = match client.get(&url).send().await.and_then(|response| response.error_for_status()) {
+            Ok(response) => {
+                let body = response.text().await.unwrap_or_default();
+                let info: serde_json::Value = serde_json::from_str(&body).unwrap_or_default();
+                let version = info["version"].as_str().unwrap_or("unknown version");
+                Check::ok("target", format!("activitywatch: {} ({})", config.activitywatch.url, version))
+            }
+            Err(e) => Check::fail(
+                "target",
+                format!("activitywatch: {} unreachable: {}", url, e),
+                "start aw-server or check activitywatch.url",
+            ),
+        };
+        checks.push(check);
+    }
+    checks
+}
+
+/// 文件存在时检查能否追加；不存在时在最近的已有上级目录中试着创建文件，上报时会自动创建中间目录
+fn check_writable(path: &Path) -> io::Result<()> {
+    if path.exists() {
+        return OpenOptions::new().append(true).open(path).map(|_| ());
+    }
+    let dir = path
+        .ancestors()
+        .skip(1)
+        .map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir })
+        .find(|dir| dir.exists())
+        .unwrap_or(Path::new("/"));
+    if !dir.is_dir() {
+        return Err(io::Error::other(format!("{} is not a directory", dir.display())));
+    }
+    let probe = dir.join(format!(".processforlinux-doctor-{}", std::process::id()));
+    OpenOptions::new().write(true).create_new(true).open(&probe)?;
+    std::fs::remove_file(&probe)
+}
+
+/// doctor 子命令：检查运行环境和配置，有失败项时返回 false
+pub async fn run(matches: &ArgMatches) -> bool {
+    let mut checks = check_session();
+
+    let config = match get_env_file::get_env_file(matches) {
+        Ok(config) => {
+            checks.push(Check::ok("config", format!("{} target(s)", config.targets.len())));
+            Some(config)
+        }
+        Err(e) => {
+            checks.push(Check::fail(
+                "config",
+                e.to_string(),
+                "run `processforlinux config show` to see the effective config",
+            ));
+            None
+        }
+    };
+
+    checks.extend(check_media(config.as_ref()));
+    if let Some(config) = &config {
+        checks.extend(check_targets(config).await);
+    }
+
+    match control::send(&control::Request::Status) {
+        Ok(_) => checks.push(Check::ok("daemon", "running")),
+        Err(_) => checks.push(Check::warn(
+            "daemon",
+            "not running",
+            "start it with `processforlinux run`",
+        )),
+    }
+
+    for check in &checks {
+        check.print();
+    }
+    !checks.iter().any(|check| check.level == Level::Fail)
+}
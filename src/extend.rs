@@ -0,0 +1,337 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/extend.rs
+ */
+
+use crate::config::{ExtendConfig, ExtendText, TextChoice};
+use crate::get_active_window::WindowInfo;
+use crate::get_media::MediaMetadata;
+use regex::Regex;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+/// 内置文案，按应用名或窗口类名精确匹配（不区分大小写）
+const BUILTIN_TEXTS: &[(&str, &str)] = &[
+    ("idea", "要么享受着kt的爽，要么就是面向Spring开发中"),
+    ("clion", "不会有人不喜欢C++吧？ 唉依赖，也是念起CMake vcpkg conan的好了"),
+    ("code", "ESLint和Prettier天天在我的配置文件里打架"),
+    ("firefox", "CSS调试唯一指定亲爹，但产品经理的电脑上没有它"),
+    ("chrome", "Lighthouse跑分专用浏览器，只要关掉插件，我的网站就天下第一"),
+    ("iterm2", "美化半天，结果99%的时间都在看 `npm install` 的进度条"),
+    ("webstorm", "自动导入一时爽，索引项目火葬场，专治各种 'any' 写法"),
+    ("pycharm", "后端同事的快乐老家，据说那里的缩进能决定项目死活"),
+    ("goland", "新潮后端们的圣杯，据说能用interface{}写出JavaScript的感觉"),
+    ("rustrover", "类型安全 无畏并发 Cargo 启动，编译慢到让人发指"),
+    ("discord", "React/Vue/Svelte 官方指定撕逼广场"),
+    ("spotify", "专注码字BGM生成器，一首歌的时间刚好够我命名一个CSS class"),
+    ("telegram", "Vite作者的日常茶馆，前端前沿资讯的第一手信源（如果你看得懂）"),
+    ("wechat", "前端兼容性噩梦的始作俑者，梦回IE6"),
+    ("qq", "内置浏览器比微信还离谱，上古前端技术展览馆"),
+    ("slack", "代码截图和部署机器人专用公告栏，以及Giphy斗图大赛主场"),
+    ("typora", "写README.md的唯一动力，毕竟它排版比我写的UI好看多了"),
+    ("vlc", "用来播放网上下载的付费教程，2倍速是基本操作"),
+    ("obs", "录制 Bug 复现视频专用，顺便幻想自己是 live-coding 大神"),
+    ("thunderbird", "GitHub和Vercel的通知轰炸区，专门用来接收构建失败的噩耗"),
+    ("kmail", "GitHub和Vercel的通知轰炸区 II：The Sequel"),
+    ("qqmusic", "当我的Babel编译卡住时，唯一能抚慰我心灵的东西"),
+    ("music", "修复IE兼容性问题时的专用BGM播放器，评论区里都是同道中人"),
+    ("yesplaymusic", "用Electron包装的听歌神器，充分体现了前端'万物皆可JS'的黑客精神"),
+    ("android studio", "Gradle syncing... @OptIn(Experimental::class)"),
+    ("sublime_text", "上古前端大神们的信仰，打开速度比我的HMR（热更新）还快"),
+    ("atom", "Electron的亲儿子，VSCode的探路石，前端圈的活化石"),
+    ("libreoffice", "当产品经理发来一个.odt格式的需求文档时，我的内心就和打开它的样式一样崩溃"),
+];
+
+/// 生成 extend 时可用的信息
+pub struct ExtendContext<'a> {
+    pub window: &'a WindowInfo,
+    pub media: Option<&'a MediaMetadata>,
+    /// 在当前应用上停留的时间
+    pub duration: chrono::Duration,
+}
+
+/// 编译好的 extend 文案表
+#[derive(Debug, Clone, Default)]
+pub struct ExtendTexts {
+    language: Option<String>,
+    builtin: bool,
+    exact: Vec<(String, ExtendText)>,
+    prefix: Vec<(String, ExtendText)>,
+    regex: Vec<(Regex, ExtendText)>,
+}
+
+impl ExtendTexts {
+    pub fn compile(config: &ExtendConfig) -> Result<Self, String> {
+        let mut texts = ExtendTexts {
+            language: config.language.clone().or_else(system_language),
+            builtin: config.builtin,
+            ..Default::default()
+        };
+        for entry in &config.texts {
+            let text = entry.text.clone();
+            match (&entry.app, &entry.prefix, &entry.regex) {
+                (Some(app), None, None) => texts.exact.push((app.to_lowercase(), text)),
+                (None, Some(prefix), None) => texts.prefix.push((prefix.to_lowercase(), text)),
+                (None, None, Some(pattern)) => {
+                    let regex = Regex::new(pattern)
+                        .map_err(|e| format!("invalid extend regex '{}': {}", pattern, e))?;
+                    texts.regex.push((regex, text));
+                }
+                _ => return Err("each extend text needs exactly one of app, prefix or regex".to_string()),
+            }
+        }
+        // 前缀越长越具体，长的先匹配
+        texts.prefix.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+        Ok(texts)
+    }
+
+    /// 按 精确 → 最长前缀 → 正则 → 内置文案 的顺序查找并展开占位符，找不到时为空
+    pub fn render(&self, context: &ExtendContext) -> String {
+        let window = context.window;
+        let names: Vec<String> = [&window.process, &window.class]
+            .into_iter()
+            .filter(|name| !name.is_empty())
+            .map(|name| name.to_lowercase())
+            .collect();
+        if names.is_empty() {
+            return String::new();
+        }
+
+        let exact = self
+            .exact
+            .iter()
+            .find(|(app, _)| names.contains(app))
+            .map(|(_, text)| text);
+        let prefix = || {
+            self.prefix
+                .iter()
+                .find(|(prefix, _)| names.iter().any(|name| name.starts_with(prefix.as_str())))
+                .map(|(_, text)| text)
+        };
+        // 正则也可以匹配窗口标题
+        let regex = || {
+            self.regex
+                .iter()
+                .find(|(regex, _)| {
+                    [&window.process, &window.class, &window.title]
+                        .iter()
+                        .any(|value| !value.is_empty() && regex.is_match(value))
+                })
+                .map(|(_, text)| text)
+        };
+
+        let template = match exact.or_else(prefix).or_else(regex) {
+            Some(text) => self.choose(text),
+            None if self.builtin => BUILTIN_TEXTS
+                .iter()
+                .find(|(app, _)| names.iter().any(|name| name == app))
+                .map(|(_, text)| text.to_string())
+                .unwrap_or_default(),
+            None => String::new(),
+        };
+        expand(&template, context)
+    }
+
+    /// 选出当前语言的文案，多条时随机取一条
+    fn choose(&self, text: &ExtendText) -> String {
+        let choice = match text {
+            ExtendText::Plain(choice) => Some(choice),
+            ExtendText::Localized(languages) => self
+                .language
+                .as_ref()
+                .and_then(|language| languages.get(language))
+                .or_else(|| languages.get("en"))
+                .or_else(|| languages.values().next()),
+        };
+        match choice {
+            Some(TextChoice::One(text)) => text.clone(),
+            Some(TextChoice::Rotation(texts)) if !texts.is_empty() => {
+                // 不需要可复现，用标准库随机种子的哈希代替随机数
+                let index = RandomState::new().hash_one(texts.len()) as usize % texts.len();
+                texts[index].clone()
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+/// 从 LC_ALL、LC_MESSAGES、LANG 中取语言代码，例如 zh_CN.UTF-8 → zh
+fn system_language() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .map(|value| {
+            value
+                .split(['_', '.', '@'])
+                .next()
+                .unwrap_or_default()
+                .to_lowercase()
+        })
+        .filter(|language| !language.is_empty() && language != "c" && language != "posix")
+}
+
+/// 1h 05m、12m、<1m
+pub fn format_duration(duration: chrono::Duration) -> String {
+    let minutes = duration.num_minutes();
+    match minutes {
+        _ if minutes < 1 => "<1m".to_string(),
+        _ if minutes < 60 => format!("{}m", minutes),
+        _ => format!("{}h {:02}m", minutes / 60, minutes % 60),
+    }
+}
+
+/// 展开 {title} 这样的占位符，不认识的占位符原样保留
+fn expand(template: &str, context: &ExtendContext) -> String {
+    let media = context.media.cloned().unwrap_or_default();
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start + 1..].find('}') else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        let name = &rest[start + 1..start + 1 + len];
+        let value = match name {
+            "process" => Some(context.window.process.clone()),
+            "class" => Some(context.window.class.clone()),
+            "exe" => Some(context.window.exe.clone()),
+            "title" => Some(context.window.title.clone()),
+            "duration" => Some(format_duration(context.duration)),
+            "media_title" => Some(media.title.clone().unwrap_or_default()),
+            "media_artist" => Some(media.artist.clone().unwrap_or_default()),
+            _ => None,
+        };
+        match value {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(&rest[start..start + len + 2]),
+        }
+        rest = &rest[start + len + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(source: &str) -> ExtendTexts {
+        let config: ExtendConfig = toml::from_str(source).unwrap();
+        ExtendTexts::compile(&config).unwrap()
+    }
+
+    fn window(process: &str, class: &str, title: &str) -> WindowInfo {
+        WindowInfo {
+            process: process.to_string(),
+            class: class.to_string(),
+            exe: String::new(),
+            title: title.to_string(),
+        }
+    }
+
+    fn render(texts: &ExtendTexts, window: &WindowInfo, media: Option<&MediaMetadata>) -> String {
+        texts.render(&ExtendContext {
+            window,
+            media,
+            duration: chrono::Duration::minutes(65),
+        })
+    }
+
+    const SOURCE: &str = r#"
+        language = "zh"
+        builtin = false
+
+        [[texts]]
+        app = "Code"
+        text = "exact {title}"
+
+        [[texts]]
+        prefix = "jetbrains"
+        text = "short prefix"
+
+        [[texts]]
+        prefix = "jetbrains-id"
+        text = "long prefix"
+
+        [[texts]]
+        regex = "\\.rs$"
+        text = "regex {process}"
+
+        [[texts]]
+        app = "firefox"
+        text = { zh = "中文", en = "english" }
+    "#;
+
+    #[test]
+    fn matches_in_priority_order() {
+        let texts = texts(SOURCE);
+        assert_eq!(render(&texts, &window("code", "", "main.rs"), None), "exact main.rs");
+        assert_eq!(render(&texts, &window("", "jetbrains-idea", "main.rs"), None), "long prefix");
+        assert_eq!(render(&texts, &window("", "jetbrains-clion", ""), None), "short prefix");
+        assert_eq!(render(&texts, &window("Helix", "", "main.rs"), None), "regex Helix");
+        assert_eq!(render(&texts, &window("Helix", "", "notes.md"), None), "");
+        assert_eq!(render(&texts, &window("", "", "main.rs"), None), "");
+    }
+
+    #[test]
+    fn picks_configured_language() {
+        let mut texts = texts(SOURCE);
+        assert_eq!(render(&texts, &window("Firefox", "", ""), None), "中文");
+        texts.language = Some("fr".to_string());
+        assert_eq!(render(&texts, &window("Firefox", "", ""), None), "english");
+    }
+
+    #[test]
+    fn falls_back_to_builtin_texts() {
+        let builtin = texts("");
+        assert_eq!(
+            render(&builtin, &window("", "VLC", ""), None),
+            "用来播放网上下载的付费教程，2倍速是基本操作"
+        );
+        assert_eq!(render(&texts("builtin = false"), &window("", "VLC", ""), None), "");
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        let texts = texts(
+            r#"
+            [[texts]]
+            app = "Spotify"
+            text = "{media_artist} - {media_title} ({duration}) {unknown} {class"
+            "#,
+        );
+        let media = MediaMetadata {
+            title: Some("Song".to_string()),
+            artist: Some("Artist".to_string()),
+            thumbnail: None,
+        };
+        assert_eq!(
+            render(&texts, &window("Spotify", "spotify", ""), Some(&media)),
+            "Artist - Song (1h 05m) {unknown} {class"
+        );
+        assert_eq!(
+            render(&texts, &window("Spotify", "spotify", ""), None),
+            " -  (1h 05m) {unknown} {class"
+        );
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(chrono::Duration::seconds(30)), "<1m");
+        assert_eq!(format_duration(chrono::Duration::minutes(12)), "12m");
+        assert_eq!(format_duration(chrono::Duration::minutes(120)), "2h 00m");
+    }
+
+    #[test]
+    fn rejects_ambiguous_entries() {
+        let config: ExtendConfig =
+            toml::from_str("[[texts]]\napp = \"a\"\nprefix = \"b\"\ntext = \"x\"").unwrap();
+        assert!(ExtendTexts::compile(&config).is_err());
+        let config: ExtendConfig = toml::from_str("[[texts]]\nregex = \"(\"\ntext = \"x\"").unwrap();
+        assert!(ExtendTexts::compile(&config).is_err());
+    }
+}
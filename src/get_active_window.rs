@@ -16,7 +16,9 @@ use std::thread;
 use std::time::Duration;
 use std::fs;
 use std::env;
+use tracing::debug;
 
+#[allow(clippy::upper_case_acronyms)] // 沿用应用本身的写法
 enum WindowTitle {
     Code,
     WebStorm,
@@ -123,6 +125,38 @@ impl WindowTitle {
     }
 }
 
+/// 活动窗口的信息，隐私规则按 class、exe、title 匹配
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WindowInfo {
+    /// 上报用的应用名，未知应用为空
+    pub process: String,
+    /// 窗口类名（X11 的 WM_CLASS，KWin 的 resourceClass）
+    pub class: String,
+    /// 可执行文件名，从 /proc/<pid>/exe 读取
+    pub exe: String,
+    pub title: String,
+}
+
+impl WindowInfo {
+    pub fn new(class: &str, title: &str, pid: Option<u32>) -> Self {
+        let process = match WindowTitle::from_string(class) {
+            // 如果是未知进程，使用空字符串而不是 "None"
+            WindowTitle::None => String::new(),
+            window_title => window_title.to_string(),
+        };
+        let exe = pid
+            .and_then(|pid| fs::read_link(format!("/proc/{}/exe", pid)).ok())
+            .and_then(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+        WindowInfo {
+            process,
+            class: class.to_string(),
+            exe,
+            title: title.to_string(),
+        }
+    }
+}
+
 /// 检测当前会话类型
 pub fn detect_session_type() -> String {
     // 首先检查 XDG_SESSION_TYPE 环境变量
@@ -145,7 +179,7 @@ pub fn detect_session_type() -> String {
 }
 
 /// 检测可用的 D-Bus 工具
-fn detect_dbus_tool() -> Option<String> {
+pub fn detect_dbus_tool() -> Option<String> {
     // 按优先级尝试不同的 D-Bus 工具
     let tools = ["qdbus6", "qdbus"];
 
@@ -159,12 +193,12 @@ fn detect_dbus_tool() -> Option<String> {
 }
 
 /// 在 Wayland KDE Plasma 环境下获取活动窗口
-fn get_active_window_wayland_kde() -> Result<String, Box<dyn Error>> {
+fn get_active_window_wayland_kde() -> Result<WindowInfo, Box<dyn Error>> {
     // 检测可用的 D-Bus 工具
     let dbus_tool = detect_dbus_tool()
         .ok_or("未找到可用的 D-Bus 工具 (qdbus6/qdbus)")?;
 
-    println!("使用 D-Bus 工具: {}", dbus_tool);
+    debug!("Using D-Bus tool {}", dbus_tool);
 
     // 创建临时的 KWin 脚本文件
     let script_content = r#"
@@ -172,7 +206,11 @@ fn get_active_window_wayland_kde() -> Result<String, Box<dyn Error>> {
 try {
     const activeWindow = workspace.activeWindow;
     if (activeWindow && activeWindow.resourceClass) {
-        print("ACTIVE_WINDOW:" + activeWindow.resourceClass.toString());
+        print("ACTIVE_WINDOW:" + JSON.stringify({
+            class: activeWindow.resourceClass.toString(),
+            title: activeWindow.caption || "",
+            pid: activeWindow.pid || 0
+        }));
     } else {
         print("ACTIVE_WINDOW:");
     }
@@ -214,7 +252,7 @@ try {
         return Err("Failed to get script ID".into());
     }
 
-    println!("KWin 脚本 ID: {}", script_id);
+    debug!("Loaded KWin script {}", script_id);
 
     // 运行脚本
     let run_output = Command::new(&dbus_tool)
@@ -256,22 +294,21 @@ try {
     }
 
     let journal_content = String::from_utf8_lossy(&journal_output.stdout);
-    println!("Journal 输出:\n{}", journal_content);
+    debug!("journalctl output:\n{}", journal_content);
 
     // 解析输出
     for line in journal_content.lines() {
         if line.contains("ACTIVE_WINDOW:") {
-            let class_name = line.split("ACTIVE_WINDOW:").nth(1).unwrap_or("").trim();
-            if !class_name.is_empty() {
-                println!("class_name (Wayland): {}", class_name);
-                let window_title_enum = WindowTitle::from_string(class_name);
-
-                // 如果是未知进程，直接返回空字符串
-                if matches!(window_title_enum, WindowTitle::None) {
-                    return Ok(String::new());
-                }
-
-                return Ok(window_title_enum.to_string());
+            let window = line.split("ACTIVE_WINDOW:").nth(1).unwrap_or("").trim();
+            if let Ok(window) = serde_json::from_str::<serde_json::Value>(window) {
+                let class_name = window["class"].as_str().unwrap_or_default();
+                debug!(class = class_name, "Active window from KWin");
+                let pid = window["pid"].as_u64().map(|pid| pid as u32).filter(|pid| *pid > 0);
+                return Ok(WindowInfo::new(
+                    class_name,
+                    window["title"].as_str().unwrap_or_default(),
+                    pid,
+                ));
             }
         }
         if line.contains("ERROR:") {
@@ -280,11 +317,11 @@ try {
         }
     }
 
-    Ok(String::new())
+    Ok(WindowInfo::default())
 }
 
 /// 在 X11 环境下获取活动窗口 (原有实现)
-fn get_active_window_x11() -> Result<String, Box<dyn Error>> {
+fn get_active_window_x11() -> Result<WindowInfo, Box<dyn Error>> {
     let mut failure_count = 0;
     let max_attempts = 5; // 最大尝试次数
 
@@ -310,8 +347,8 @@ fn get_active_window_x11() -> Result<String, Box<dyn Error>> {
         if window_id.is_empty() {
             failure_count += 1;
             if failure_count >= max_attempts {
-                println!("无法获取活动窗口，返回空字符串");
-                return Ok(String::new());
+                debug!("No active window after {} attempts", max_attempts);
+                return Ok(WindowInfo::default());
             }
             thread::sleep(Duration::from_millis(200));
             continue;
@@ -321,60 +358,74 @@ fn get_active_window_x11() -> Result<String, Box<dyn Error>> {
             .arg("-id")
             .arg(&window_id)
             .arg("WM_CLASS")
+            .arg("_NET_WM_NAME")
+            .arg("_NET_WM_PID")
             .stdout(Stdio::piped())
             .spawn()?
             .stdout
             .ok_or("Failed to capture xprop stdout")?;
 
         let xprop_reader = BufReader::new(xprop_output);
+        let mut class_name = None;
+        let mut title = String::new();
+        let mut pid = None;
         for line in xprop_reader.lines() {
             let line = line?;
             if line.contains("WM_CLASS(STRING)") {
-                let class_name = line.split('"').nth(1).unwrap_or("");
-                println!("class_name (X11): {}", class_name);
-                let window_title_enum = WindowTitle::from_string(class_name);
-
-                // 如果是未知进程，直接返回空字符串而不是 "None"
-                if matches!(window_title_enum, WindowTitle::None) {
-                    return Ok(String::new());
+                class_name = Some(line.split('"').nth(1).unwrap_or("").to_string());
+            } else if line.starts_with("_NET_WM_NAME") {
+                // 标题里可能有引号，取第一个和最后一个引号之间的内容
+                if let (Some(start), Some(end)) = (line.find('"'), line.rfind('"')) {
+                    if start < end {
+                        title = line[start + 1..end].replace("\\\"", "\"");
+                    }
                 }
-
-                return Ok(window_title_enum.to_string());
+            } else if line.starts_with("_NET_WM_PID") {
+                pid = line.split_whitespace().last().and_then(|pid| pid.parse().ok());
             }
         }
+        if let Some(class_name) = class_name {
+            debug!(class = %class_name, "Active window from xprop");
+            return Ok(WindowInfo::new(&class_name, &title, pid));
+        }
 
         failure_count += 1;
         if failure_count >= max_attempts {
-            println!("达到最大尝试次数，返回空字符串");
-            return Ok(String::new());
+            debug!("No WM_CLASS after {} attempts", max_attempts);
+            return Ok(WindowInfo::default());
         }
         thread::sleep(Duration::from_millis(200));
     }
 }
 
-pub fn get_active_window_process_and_title() -> Result<String, Box<dyn Error>> {
+/// 是否是 KDE Plasma 会话
+pub fn is_kde_session() -> bool {
+    env::var("KDE_SESSION_VERSION").is_ok()
+        || env::var("DESKTOP_SESSION").unwrap_or_default().contains("plasma")
+        || env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().contains("KDE")
+}
+
+/// 获取活动窗口的应用名、类名、可执行文件名和标题
+pub fn get_active_window_info() -> Result<WindowInfo, Box<dyn Error>> {
     let session_type = detect_session_type();
-    println!("检测到的会话类型: {}", session_type);
 
     match session_type.as_str() {
         "wayland" => {
             // 检查是否是 KDE Plasma
-            if env::var("KDE_SESSION_VERSION").is_ok() ||
-               env::var("DESKTOP_SESSION").unwrap_or_default().contains("plasma") ||
-               env::var("XDG_CURRENT_DESKTOP").unwrap_or_default().contains("KDE") {
-                println!("检测到 KDE Plasma Wayland 环境，使用 KWin 脚本方法");
+            if is_kde_session() {
+                debug!(session = %session_type, "Using KWin script");
                 get_active_window_wayland_kde()
             } else {
-                println!("Wayland 环境下非 KDE，暂不支持");
-                Ok(String::new())
+                debug!(session = %session_type, "Active window detection is not supported on this Wayland compositor");
+                Ok(WindowInfo::default())
             }
         }
         "x11" => {
-            println!("检测到 X11 环境，使用 xprop 方法");
+            debug!(session = %session_type, "Using xprop");
             get_active_window_x11()
         }
         _ => {
-            println!("未知的会话类型 ({}), 尝试 X11 方法", session_type);
+            debug!(session = %session_type, "Unknown session type, trying xprop");
             get_active_window_x11()
         }
     }
@@ -5,35 +5,75 @@
  * @LastEditTime: 2023-08-14 16:37:28
  * @FilePath: /processforlinux/src/get_env_file.rs
  */
-use clap::{Command, Arg};
+use crate::auth::Secret;
+use crate::config::{self, Config, ConfigError, Source, TargetConfig};
+use crate::pause;
+use crate::secret_store;
+use clap::{ArgMatches, Command, Arg};
 use std::error::Error;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, PartialEq)]
-struct ConfigError(String);
+/// 旧版 .env.process 的默认文件名，兼容从当前目录读取
+const LEGACY_CONFIG_FILE: &str = ".env.process";
 
-impl std::fmt::Display for ConfigError {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "{}", self.0)
-    }
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_lowercase())
+        .filter(|item| !item.is_empty())
+        .collect()
 }
 
-impl Error for ConfigError {}
-
-struct UserConfig {
-    api_url: String,
-    api_key: String,
-    watch_time: i64,
-    media_enable: bool,
-    log_enable: bool,
+/// 解析 TARGET_<NAME>_<KEY> 形式的键
+fn apply_target_key(target: &mut TargetConfig, key: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    let field = match key {
+        "URL" | "API_URL" => "url".to_string(),
+        "MEDIA_ENABLE" => "media".to_string(),
+        "EXTEND_ENABLE" => "extend".to_string(),
+        "TEMPLATE" => {
+            // 模板可以直接写 JSON，也可以写模板文件路径
+            let trimmed = value.trim();
+            if trimmed.starts_with('{') || trimmed.starts_with('[') || trimmed.starts_with('"') {
+                "template".to_string()
+            } else {
+                "template_file".to_string()
+            }
+        }
+        // TARGET_X_HEADER_X_TITLE -> x-title
+        _ if key.starts_with("HEADER_") => {
+            format!("headers.{}", key["HEADER_".len()..].to_lowercase().replace('_', "-"))
+        }
+        "TYPE" | "API_KEY" | "AUTH" | "AUTH_HEADER" | "AUTH_USER" | "PATH" | "EXCLUDE" | "METHOD" => {
+            key.to_lowercase()
+        }
+        _ => {
+            return Err(ConfigError(format!(
+                "Unknown key TARGET_{}_{}",
+                target.name.to_uppercase(),
+                key
+            ))
+            .into())
+        }
+    };
+    target.set(&field, value).map_err(|e| {
+        ConfigError(format!("TARGET_{}_{}: {}", target.name.to_uppercase(), key, e)).into()
+    })
 }
 
-fn read_config_values(config_path: &str) -> Result<UserConfig, Box<dyn Error>> {
+/// 读取旧版 KEY=value 格式的配置，转换为 Config（尚未校验）
+fn read_config_values(config_path: &Path) -> Result<Config, Box<dyn Error>> {
     let file = File::open(config_path)?;
     let reader = BufReader::new(file);
-    let (mut api_url, mut api_key, mut watch_time, mut media_enable, mut log_enable) =
-        (None, None, None, None, None);
+    let mut config = Config::default();
+    let (mut api_url, mut api_key) = (None, None);
+    // 旧式的单目标配置，默认只有 Mix Space
+    let mut reporters = vec!["mixspace".to_string()];
+    let (mut webhook_url, mut jsonl_path, mut unix_socket_path) = (None, None, None);
+    // 多目标配置：TARGETS 声明目标列表，TARGET_<NAME>_* 给出各自的设置
+    let mut target_names: Option<Vec<String>> = None;
+    let mut target_keys = Vec::new();
 
     for line_result in reader.lines() {
         let line = line_result?;
@@ -47,9 +87,21 @@ fn read_config_values(config_path: &str) -> Result<UserConfig, Box<dyn Error>> {
             match key {
                 "API_URL" => api_url = Some(value.to_string()),
                 "API_KEY" => api_key = Some(value.to_string()),
-                "WATCH_TIME" => watch_time = Some(value.parse()?),
-                "MEDIA_ENABLE" => media_enable = Some(value.parse()?),
-                "LOG_ENABLE" => log_enable = Some(value.parse()?),
+                "WATCH_TIME" => config.watch_time = value.parse()?,
+                "MEDIA_ENABLE" => config.media.enable = value.parse()?,
+                "LOG_ENABLE" => config.log_enable = value.parse()?,
+                "QUEUE_ENABLE" => config.queue.enable = value.parse()?,
+                "QUEUE_DIR" => config.queue.dir = Some(PathBuf::from(value)),
+                "QUEUE_MAX_ENTRIES" => config.queue.max_entries = value.parse()?,
+                "QUEUE_MAX_AGE" => config.queue.max_age = value.parse()?,
+                "REPORTERS" => reporters = split_list(value),
+                "WEBHOOK_URL" => webhook_url = Some(value.to_string()),
+                "JSONL_PATH" => jsonl_path = Some(PathBuf::from(value)),
+                "UNIX_SOCKET_PATH" => unix_socket_path = Some(PathBuf::from(value)),
+                "TARGETS" => target_names = Some(split_list(value)),
+                _ if key.starts_with("TARGET_") => {
+                    target_keys.push((key.to_string(), value.to_string()))
+                }
                 _ => {
                     // Handle unknown or invalid key-value pairs
                     eprintln!("Unknown key-value pair: {}", trimmed_line);
@@ -58,46 +110,436 @@ fn read_config_values(config_path: &str) -> Result<UserConfig, Box<dyn Error>> {
         }
     }
 
-    Ok(UserConfig {
-        api_url: api_url.ok_or_else(|| ConfigError("API_URL not set".to_string()))?,
-        api_key: api_key.ok_or_else(|| ConfigError("API_KEY not set".to_string()))?,
-        watch_time: watch_time.ok_or_else(|| ConfigError("WATCH_TIME not set".to_string()))?,
-        media_enable: media_enable
-            .ok_or_else(|| ConfigError("MEDIA_ENABLE not set".to_string()))?,
-        log_enable: log_enable.ok_or_else(|| ConfigError("LOG_ENABLE not set".to_string()))?,
-    })
+    config.targets = match target_names {
+        Some(names) => {
+            let mut targets: Vec<TargetConfig> =
+                names.iter().map(|name| TargetConfig::new(name, "mixspace")).collect();
+            for (key, value) in &target_keys {
+                let rest = &key["TARGET_".len()..];
+                // 目标名可能包含下划线，取最长匹配的目标名
+                let target = targets
+                    .iter_mut()
+                    .filter(|target| {
+                        rest.to_lowercase()
+                            .strip_prefix(&target.name)
+                            .is_some_and(|tail| tail.starts_with('_'))
+                    })
+                    .max_by_key(|target| target.name.len())
+                    .ok_or_else(|| ConfigError(format!("{} refers to an undeclared target", key)))?;
+                let target_key = rest[target.name.len() + 1..].to_string();
+                apply_target_key(target, &target_key, value)?;
+            }
+            targets
+        }
+        None => {
+            if !target_keys.is_empty() {
+                return Err(ConfigError("TARGET_* keys require TARGETS to be set".to_string()).into());
+            }
+            reporters
+                .iter()
+                .map(|kind| {
+                    let mut target = TargetConfig::new(kind, kind);
+                    match kind.as_str() {
+                        "mixspace" => {
+                            target.url = api_url.clone();
+                            target.api_key = api_key.clone();
+                        }
+                        "webhook" => target.url = webhook_url.clone(),
+                        "jsonl" => target.path = jsonl_path.clone(),
+                        "unix" => target.path = unix_socket_path.clone(),
+                        _ => {}
+                    }
+                    target
+                })
+                .collect()
+        }
+    };
+
+    Ok(config)
 }
 
-pub fn get_env_file() -> Result<(String, String, i64, bool, bool, bool), Box<dyn Error>> {
-    let matches = Command::new("Process Report For Linux")
+/// 全局配置项对应的命令行参数，例如 log.max_size 为 --log-max-size
+fn override_flag(key: &str) -> String {
+    key.replace(['.', '_'], "-")
+}
+
+pub fn cli() -> Command {
+    let mut command = Command::new("Process Report For Linux")
         .arg(
             Arg::new("config")
                 .short('c')
                 .long("config")
                 .value_name("FILE")
                 .num_args(1)
-                .default_value(".env.process") // Set default config file path
-                .help("Sets the config file path"),
+                .global(true)
+                .help("Sets the config file path (default: $XDG_CONFIG_HOME/processforlinux/config.toml, falling back to ./.env.process)"),
         )
         .arg(
             Arg::new("no-gui")
                 .long("no-gui")
                 .action(clap::ArgAction::SetTrue)
+                .global(true)
                 .help("Disable GUI mode and use console mode")
         )
-        .get_matches();
-
-    let config_file = matches.get_one::<String>("config").unwrap();
-    let config_path = std::env::current_dir()?.join(config_file);
-    let gui_enable = !matches.get_flag("no-gui"); // 默认启用GUI，除非指定--no-gui
-
-    let user_config = read_config_values(config_path.to_str().unwrap())?;
-    Ok((
-        user_config.api_url,
-        user_config.api_key,
-        user_config.watch_time,
-        user_config.media_enable,
-        user_config.log_enable,
-        gui_enable, // 添加GUI选项
-    ))
+        .arg(
+            Arg::new("set")
+                .long("set")
+                .value_name("KEY=VALUE")
+                .action(clap::ArgAction::Append)
+                .global(true)
+                .help("Override any config key, e.g. --set targets.blog.url=https://... (repeatable)"),
+        );
+
+    // 每个全局配置项都有对应的参数，优先级高于环境变量和配置文件
+    for key in config::GLOBAL_KEYS {
+        let flag = override_flag(key);
+        command = command.arg(
+            Arg::new(flag.clone())
+                .long(flag)
+                .value_name("VALUE")
+                .global(true)
+                .help(format!("Override {} (env {}{})", key, config::ENV_PREFIX, key.to_uppercase().replace('.', "_"))),
+        );
+    }
+
+    let format = || {
+        Arg::new("format")
+            .long("format")
+            .value_parser(["text", "json"])
+            .default_value("text")
+            .help("Output format")
+    };
+
+    command
+        .subcommand(Command::new("run").about("Run the reporter daemon (default)"))
+        .subcommand(
+            Command::new("once")
+                .about("Detect and print the current window and media without reporting")
+                .arg(format())
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Also show what each target would be sent after schedules and app rules"),
+                )
+                .arg(Arg::new("class").long("class").value_name("CLASS").help("Pretend the active window has this class"))
+                .arg(Arg::new("exe").long("exe").value_name("EXE").help("Pretend the active window has this executable"))
+                .arg(Arg::new("title").long("title").value_name("TITLE").help("Pretend the active window has this title")),
+        )
+        .subcommand(
+            Command::new("report")
+                .about("Summarize time spent per app, category or hour from the local history")
+                .args(range_args())
+                .arg(
+                    Arg::new("by")
+                        .long("by")
+                        .value_parser(["app", "category", "hour"])
+                        .default_value("app")
+                        .help("Group totals by app, by [categories] or by hour"),
+                )
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_parser(["table", "json", "csv", "markdown"])
+                        .default_value("table")
+                        .help("Output format"),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Export the local history, e.g. as ActivityWatch buckets for aw-server's import")
+                .args(range_args())
+                .arg(
+                    Arg::new("format")
+                        .long("format")
+                        .value_parser(["activitywatch", "jsonl"])
+                        .default_value("activitywatch")
+                        .help("Output format"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .value_name("FILE")
+                        .help("Write to FILE instead of stdout"),
+                ),
+        )
+        .subcommand(
+            Command::new("status")
+                .about("Show the status of the running daemon")
+                .arg(format()),
+        )
+        .subcommand(
+            Command::new("pause")
+                .about("Pause reporting of the running daemon")
+                .arg(
+                    Arg::new("minutes")
+                        .value_name("MINUTES")
+                        .value_parser(clap::value_parser!(u64).range(1..=pause::MAX_PAUSE_MINUTES))
+                        .help("Resume automatically after this many minutes (at most a week)"),
+                ),
+        )
+        .subcommand(Command::new("resume").about("Resume reporting of the running daemon"))
+        .subcommand(Command::new("report-now").about("Make the running daemon report immediately"))
+        .subcommand(Command::new("reload").about("Make the running daemon reload its config"))
+        .subcommand(Command::new("show-overlay").about("Show the running daemon's overlay again after hiding it"))
+        .subcommand(Command::new("events").about("Print the running daemon's event stream as JSON lines"))
+        .subcommand(
+            Command::new("doctor")
+                .about("Check the session, backends, D-Bus, MPRIS players and report targets"),
+        )
+        .subcommand(
+            Command::new("install-service")
+                .about("Install and start a systemd user service for the current session and arguments")
+                .arg(
+                    Arg::new("no-enable")
+                        .long("no-enable")
+                        .action(clap::ArgAction::SetTrue)
+                        .help("Only write the unit file, do not enable or start it"),
+                ),
+        )
+        .subcommand(Command::new("uninstall-service").about("Stop and remove the systemd user service"))
+        .subcommand(
+            Command::new("install-autostart")
+                .about("Start on desktop login via ~/.config/autostart, with the current arguments"),
+        )
+        .subcommand(Command::new("uninstall-autostart").about("Remove the autostart entry"))
+        .subcommand(
+            Command::new("set-key")
+                .about("Store an API key in the Secret Service keyring (read from stdin)")
+                .arg(
+                    Arg::new("reference")
+                        .value_name("keyring:SERVICE/ACCOUNT")
+                        .default_value("keyring:processforlinux/main")
+                        .help("Keyring reference to use as api_key in the config file"),
+                ),
+        )
+        .subcommand(
+            Command::new("config")
+                .about("Inspect the configuration")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("show")
+                        .about("Print the effective config and where each value comes from"),
+                ),
+        )
+}
+
+/// 按扩展名选择解析方式：.toml 为新格式，其他按旧版 .env.process 处理
+fn read_config_file(path: &Path) -> Result<(Config, Option<String>), Box<dyn Error>> {
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        let (config, source) = config::read_toml(path)?;
+        Ok((config, Some(source)))
+    } else {
+        Ok((read_config_values(path)?, None))
+    }
+}
+
+/// report 和 export 共用的时间范围参数
+fn range_args() -> [Arg; 3] {
+    [
+        Arg::new("range")
+            .value_name("RANGE")
+            .value_parser(["today", "yesterday", "week", "last-week"])
+            .default_value("today")
+            .help("Period to cover; week starts on Monday"),
+        Arg::new("from")
+            .long("from")
+            .value_name("YYYY-MM-DD")
+            .help("First day of a custom range"),
+        Arg::new("to")
+            .long("to")
+            .value_name("YYYY-MM-DD")
+            .help("Last day of a custom range (default: today)"),
+    ]
+}
+
+/// 实际使用的配置文件：--config 指定的文件，否则依次查找 config.toml 和 ./.env.process
+pub fn config_path(matches: &ArgMatches) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    if let Some(config_file) = matches.get_one::<String>("config") {
+        return Ok(Some(std::env::current_dir()?.join(config_file)));
+    }
+
+    let default_path = config::default_config_path();
+    let legacy_path = std::env::current_dir()?.join(LEGACY_CONFIG_FILE);
+    if default_path.exists() {
+        Ok(Some(default_path))
+    } else if legacy_path.exists() {
+        Ok(Some(legacy_path))
+    } else {
+        Ok(None)
+    }
+}
+
+/// 服务和自启动项使用的 run 命令：程序的绝对路径，以及非默认位置的配置文件和覆盖配置的参数
+pub fn run_args(matches: &ArgMatches) -> Result<Vec<String>, Box<dyn Error>> {
+    let exe = std::env::current_exe()?.canonicalize()?;
+    let mut args = vec![exe.display().to_string(), "run".to_string()];
+    if let Some(path) = config_path(matches)? {
+        if path != config::default_config_path() {
+            args.push("--config".to_string());
+            args.push(path.canonicalize().unwrap_or(path).display().to_string());
+        }
+    }
+    args.extend(override_args(matches));
+    Ok(args)
+}
+
+/// 覆盖配置的命令行参数：--no-gui、--set 和各配置项的参数，不含 --config
+pub fn override_args(matches: &ArgMatches) -> Vec<String> {
+    let mut args = Vec::new();
+    if matches.get_flag("no-gui") {
+        args.push("--no-gui".to_string());
+    }
+    for key in config::GLOBAL_KEYS {
+        let flag = override_flag(key);
+        if let Some(value) = matches.get_one::<String>(&flag) {
+            args.push(format!("--{}", flag));
+            args.push(value.clone());
+        }
+    }
+    for value in matches.get_many::<String>("set").into_iter().flatten() {
+        args.push("--set".to_string());
+        args.push(value.clone());
+    }
+    args
+}
+
+/// 按 命令行 > 环境变量 > 配置文件 > 默认值 的顺序合并配置，尚未校验
+///
+/// 返回配置、出处（用于错误信息）和 TOML 原文
+fn load_layers(matches: &ArgMatches) -> Result<(Config, String, Option<String>), Box<dyn Error>> {
+    let path = config_path(matches)?;
+
+    let (mut config, origin, source) = match &path {
+        Some(path) => {
+            let (mut config, source) = read_config_file(path)?;
+            let origin = path.display().to_string();
+            config.mark_file_sources(&origin);
+            (config, origin, source)
+        }
+        None => (Config::default(), "overrides".to_string(), None),
+    };
+
+    config.apply_env(std::env::vars())?;
+
+    for key in config::GLOBAL_KEYS {
+        let flag = override_flag(key);
+        if let Some(value) = matches.get_one::<String>(&flag) {
+            config.set_from(key, value, Source::Cli(format!("--{}", flag)))?;
+        }
+    }
+    for assignment in matches.get_many::<String>("set").into_iter().flatten() {
+        let (key, value) = assignment.split_once('=').ok_or_else(|| {
+            ConfigError(format!("--set expects KEY=VALUE, got '{}'", assignment))
+        })?;
+        config.set_from(key.trim(), value, Source::Cli("--set".to_string()))?;
+    }
+    if matches.get_flag("no-gui") {
+        config.set_from("gui.enable", "false", Source::Cli("--no-gui".to_string()))?;
+    }
+
+    // 既没有配置文件，环境变量和命令行也没有给出目标
+    if path.is_none() && config.targets.is_empty() {
+        return Err(ConfigError(format!(
+            "No config file found, create {} (or {} in the current directory)",
+            config::default_config_path().display(),
+            LEGACY_CONFIG_FILE
+        ))
+        .into());
+    }
+    Ok((config, origin, source))
+}
+
+/// 读取配置但不校验，用于不需要上报的子命令
+pub fn load_unchecked(matches: &ArgMatches) -> Result<Config, Box<dyn Error>> {
+    load_layers(matches).map(|(config, _, _)| config)
+}
+
+pub fn get_env_file(matches: &ArgMatches) -> Result<Config, Box<dyn Error>> {
+    let (mut config, origin, source) = load_layers(matches)?;
+    config.finalize(&origin, source.as_deref())?;
+    Ok(config)
+}
+
+/// config show 子命令：打印合并后的配置和每一项的来源，密钥会被遮盖
+pub fn show_config(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let (config, origin, source) = load_layers(matches)?;
+
+    let entries = config.entries();
+    let width = entries.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, value) in &entries {
+        println!("{:<width$} = {:<40} # {}", key, value, config.source_of(key), width = width);
+    }
+
+    // 最后做一次完整校验（包括解析密钥引用），让问题在启动前暴露
+    config.clone().finalize(&origin, source.as_deref())?;
+    Ok(())
+}
+/// set-key 子命令：从标准输入读取密钥并存入钥匙环
+pub fn set_key(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let reference = matches.get_one::<String>("reference").unwrap();
+
+    let key = if std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+        eprint!("API key for {}: ", reference);
+        read_hidden_line()?
+    } else {
+        let mut key = String::new();
+        std::io::stdin().read_line(&mut key)?;
+        key
+    };
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(ConfigError("API key is empty".to_string()).into());
+    }
+
+    secret_store::store_keyring(reference, &Secret::new(key))?;
+    println!("Stored. Use api_key = \"{}\" in your config file.", reference);
+    Ok(())
+}
+
+/// 从终端读取一行，读取时关闭回显，密钥不会留在屏幕和回滚记录里
+fn read_hidden_line() -> std::io::Result<String> {
+    use std::os::fd::AsRawFd;
+
+    let stdin = std::io::stdin();
+    let fd = stdin.as_raw_fd();
+    // SAFETY: termios 是普通的 C 结构体，由 tcgetattr 填充
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut hidden = original;
+    // 不回显输入，但仍然显示回车后的换行
+    hidden.c_lflag &= !libc::ECHO;
+    hidden.c_lflag |= libc::ECHONL;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &hidden) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mut line = String::new();
+    let result = stdin.read_line(&mut line);
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    result.map(|_| line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli_is_valid() {
+        cli().debug_assert();
+    }
+
+    #[test]
+    fn every_global_key_has_a_flag() {
+        for key in config::GLOBAL_KEYS {
+            let flag = format!("--{}", override_flag(key));
+            let matches = cli()
+                .try_get_matches_from(["processforlinux", flag.as_str(), "1"])
+                .unwrap_or_else(|e| panic!("{}: {}", flag, e));
+            assert_eq!(matches.get_one::<String>(&override_flag(key)).map(String::as_str), Some("1"));
+        }
+    }
 }
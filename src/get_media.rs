@@ -9,6 +9,7 @@ use dbus::arg::RefArg;
 use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
 use dbus::blocking::Connection;
 use dbus::blocking::Proxy;
+use tracing::debug;
 
 #[derive(Clone, PartialEq, Default, Debug)]
 pub struct MediaMetadata {
@@ -19,11 +20,6 @@ pub struct MediaMetadata {
 
 
 mod constants {
-    pub const MEDIA_PLAYER_IDENTIFIERS: [&str; 3] = [
-        "org.mpris.MediaPlayer2.yesplaymusic",
-        "org.mpris.MediaPlayer2.netease-cloud-music",
-        "org.mpris.MediaPlayer2.spotify"
-    ];
     pub const MPRIS_PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
     pub const METADATA_PROPERTY: &str = "Metadata";
 }
@@ -33,8 +29,8 @@ mod media {
     pub const ART_URL_KEY: &str = "mpris:artUrl";
 }
 
-pub fn get_media_metadata() -> Option<MediaMetadata> {
-    for &identifier in &constants::MEDIA_PLAYER_IDENTIFIERS {
+pub fn get_media_metadata(players: &[String]) -> Option<MediaMetadata> {
+    for identifier in players {
         if let Ok(connection) = Connection::new_session() {
             let proxy_result: Result<Proxy<&Connection>, dbus::Error> = Ok(connection.with_proxy(
                 identifier,
@@ -80,11 +76,11 @@ pub fn get_media_metadata() -> Option<MediaMetadata> {
                                 let artists_str = artists.join(", ");
                                 Some(artists_str)
                             } else {
-                                println!("No artist information available.");
+                                debug!("No artist information available");
                                 None
                             }
                         } else {
-                            println!("Unknown artist format.");
+                            debug!("Unknown artist format");
                             None
                         }
                     }
@@ -0,0 +1,312 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/history.rs
+ */
+
+use crate::config::HistoryConfig;
+use crate::get_active_window::WindowInfo;
+use crate::get_media::MediaMetadata;
+use chrono::{DateTime, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tracing::{error, info, warn};
+
+/// 进行中的时间段最长多久写一次盘，异常退出时最多丢失这么长的记录
+const CHECKPOINT_SECS: i64 = 300;
+
+/// 一段连续停留在同一个窗口上的时间
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// 上报用的应用名，未知应用为空
+    pub app: String,
+    pub class: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub exe: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media: Option<SpanMedia>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpanMedia {
+    pub title: String,
+    pub artist: String,
+}
+
+impl Span {
+    /// 是否还是同一段：应用、标题和媒体都没变
+    fn continues(&self, other: &Span) -> bool {
+        self.app == other.app
+            && self.class == other.class
+            && self.title == other.title
+            && self.media == other.media
+    }
+}
+
+/// 本地活动历史：每天一个 JSON Lines 文件，只追加，每行一个时间段
+pub struct History {
+    dir: PathBuf,
+    retention_days: u32,
+    titles: bool,
+    /// 检测间隔（秒），两次检测隔得太久时不把中间的时间算进去
+    watch_time: i64,
+    current: Option<Span>,
+    last_cleanup: Option<NaiveDate>,
+}
+
+impl History {
+    pub fn open(config: &HistoryConfig, watch_time: i64) -> Result<Self, Box<dyn Error>> {
+        let dir = config.dir();
+        fs::create_dir_all(&dir)?;
+        Ok(History {
+            dir,
+            retention_days: config.retention_days,
+            titles: config.titles,
+            watch_time,
+            current: None,
+            last_cleanup: None,
+        })
+    }
+
+    /// 记录一次检测结果；window 为 None（暂停、检测失败）时结束当前时间段
+    pub fn observe(&mut self, now: DateTime<Utc>, window: Option<&WindowInfo>, media: Option<&MediaMetadata>) {
+        let observed = window
+            .filter(|window| !window.class.is_empty() || !window.process.is_empty())
+            .map(|window| Span {
+                start: now,
+                end: now,
+                app: window.process.clone(),
+                class: window.class.clone(),
+                exe: window.exe.clone(),
+                title: if self.titles { window.title.clone() } else { String::new() },
+                media: media
+                    .filter(|media| media.title.as_ref().is_some_and(|title| !title.is_empty()))
+                    .map(|media| SpanMedia {
+                        title: media.title.clone().unwrap_or_default(),
+                        artist: media.artist.clone().unwrap_or_default(),
+                    }),
+            });
+
+        // 休眠、锁屏或者循环卡住时，中间的时间不算在上一个窗口上，时间段在上一次检测时结束
+        if let Some(current) = &self.current {
+            if (now - current.end).num_seconds() > 2 * self.watch_time.max(1) {
+                let end = current.end;
+                self.finish(end);
+            }
+        }
+
+        match (&mut self.current, observed) {
+            (Some(current), Some(observed)) if current.continues(&observed) => {
+                current.end = now;
+                // 长时间停留在同一个窗口时分段写入，读取时相邻的同名时间段可以直接相加
+                if (now - current.start).num_seconds() >= CHECKPOINT_SECS {
+                    self.finish(now);
+                    self.current = Some(observed);
+                }
+            }
+            (_, observed) => {
+                self.finish(now);
+                self.current = observed;
+            }
+        }
+        self.cleanup(now);
+    }
+
+    /// 结束并写入当前时间段
+    pub fn finish(&mut self, now: DateTime<Utc>) {
+        let Some(mut span) = self.current.take() else {
+            return;
+        };
+        span.end = now;
+        if span.end <= span.start {
+            return;
+        }
+        if let Err(e) = self.append(&span) {
+            error!("Failed to write history to {}: {}", self.dir.display(), e);
+        }
+    }
+
+    fn append(&self, span: &Span) -> Result<(), Box<dyn Error>> {
+        // 按开始时间的本地日期分文件
+        let date = span.start.with_timezone(&Local).date_naive();
+        let path = self.dir.join(format!("{}.jsonl", date.format("%Y-%m-%d")));
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(span)?)?;
+        Ok(())
+    }
+
+    /// 每天删除一次超过保留天数的文件，retention_days 为 0 表示一直保留
+    fn cleanup(&mut self, now: DateTime<Utc>) {
+        let today = now.with_timezone(&Local).date_naive();
+        if self.retention_days == 0 || self.last_cleanup == Some(today) {
+            return;
+        }
+        self.last_cleanup = Some(today);
+
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let oldest = today - chrono::Duration::days(self.retention_days as i64 - 1);
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let date = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .filter(|_| path.extension().is_some_and(|ext| ext == "jsonl"))
+                .and_then(|stem| NaiveDate::parse_from_str(stem, "%Y-%m-%d").ok());
+            if date.is_some_and(|date| date < oldest) {
+                match fs::remove_file(&path) {
+                    Ok(()) => info!("Removed expired history {}", path.display()),
+                    Err(e) => warn!("Failed to remove {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+}
+
+/// 读取 [from, to] 这几天（本地日期）的历史文件，损坏的行会被跳过
+///
+/// 前一天的文件也会读取，因为跨过午夜的时间段记在开始那天
+pub fn read(dir: &Path, from: NaiveDate, to: NaiveDate) -> Result<Vec<Span>, Box<dyn Error>> {
+    let mut spans = Vec::new();
+    let first = from.pred_opt().unwrap_or(from);
+    for date in first.iter_days().take_while(|date| *date <= to) {
+        let path = dir.join(format!("{}.jsonl", date.format("%Y-%m-%d")));
+        if !path.exists() {
+            continue;
+        }
+        for line in BufReader::new(File::open(&path)?).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Span>(&line) {
+                Ok(span) => spans.push(span),
+                Err(e) => warn!("Skipping malformed history entry in {}: {}", path.display(), e),
+            }
+        }
+    }
+    spans.sort_by_key(|span| span.start);
+    Ok(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use chrono::TimeZone;
+
+    /// 返回的 TempDir 要一直持有，离开作用域时删除历史目录
+    fn temp_history(name: &str, retention_days: u32) -> (TempDir, History) {
+        let temp = TempDir::new(&format!("history-{}", name));
+        let config = HistoryConfig {
+            dir: Some(temp.join("history")),
+            retention_days,
+            titles: false,
+            ..Default::default()
+        };
+        (temp, History::open(&config, 5).unwrap())
+    }
+
+    fn spans(history: &History) -> Vec<Span> {
+        let mut spans = Vec::new();
+        for entry in fs::read_dir(&history.dir).unwrap().flatten() {
+            let content = fs::read_to_string(entry.path()).unwrap();
+            spans.extend(content.lines().filter_map(|line| serde_json::from_str::<Span>(line).ok()));
+        }
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+
+    #[test]
+    fn records_a_span_per_window() {
+        let (_temp, mut history) = temp_history("spans", 0);
+        let code = WindowInfo::new("code", "main.rs", None);
+        let firefox = WindowInfo::new("firefox", "docs", None);
+        let start = Utc.with_ymd_and_hms(2026, 10, 18, 9, 0, 0).unwrap();
+        let at = |secs| start + chrono::Duration::seconds(secs);
+
+        history.observe(at(0), Some(&code), None);
+        history.observe(at(5), Some(&code), None);
+        history.observe(at(10), Some(&firefox), None);
+        // 暂停或检测失败时结束当前时间段
+        history.observe(at(15), None, None);
+
+        let spans = spans(&history);
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].class.as_str(), spans[0].start, spans[0].end), ("code", at(0), at(10)));
+        assert_eq!((spans[1].class.as_str(), spans[1].start, spans[1].end), ("firefox", at(10), at(15)));
+        assert!(spans.iter().all(|span| span.title.is_empty()));
+    }
+
+    #[test]
+    fn removes_files_past_retention() {
+        let (_temp, mut history) = temp_history("retention", 2);
+        let now = Local.with_ymd_and_hms(2026, 10, 18, 9, 0, 0).unwrap();
+        for date in ["2026-10-16", "2026-10-17", "notes"] {
+            fs::write(history.dir.join(format!("{}.jsonl", date)), "").unwrap();
+        }
+
+        history.observe(now.with_timezone(&Utc), None, None);
+
+        let mut names: Vec<String> = fs::read_dir(&history.dir)
+            .unwrap()
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["2026-10-17.jsonl", "notes.jsonl"]);
+    }
+
+    #[test]
+    fn splits_span_after_gap() {
+        let (_temp, mut history) = temp_history("gap", 0);
+        let window = WindowInfo::new("code", "main.rs", None);
+        let start = Utc.with_ymd_and_hms(2026, 10, 18, 9, 0, 0).unwrap();
+        let at = |secs| start + chrono::Duration::seconds(secs);
+
+        history.observe(at(0), Some(&window), None);
+        history.observe(at(5), Some(&window), None);
+        history.observe(at(10), Some(&window), None);
+        // 休眠了两个小时
+        history.observe(at(7210), Some(&window), None);
+        history.observe(at(7215), Some(&window), None);
+        history.finish(at(7220));
+
+        let spans = spans(&history);
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].start, spans[0].end), (at(0), at(10)));
+        assert_eq!((spans[1].start, spans[1].end), (at(7210), at(7220)));
+    }
+
+    #[test]
+    fn keeps_span_within_interval() {
+        let (_temp, mut history) = temp_history("steady", 0);
+        let window = WindowInfo::new("code", "main.rs", None);
+        let start = Utc.with_ymd_and_hms(2026, 10, 18, 9, 0, 0).unwrap();
+        let at = |secs| start + chrono::Duration::seconds(secs);
+
+        // 稍有延迟（不超过两个间隔）仍然是同一段
+        for secs in [0, 5, 14, 20] {
+            history.observe(at(secs), Some(&window), None);
+        }
+        history.finish(at(25));
+
+        let spans = spans(&history);
+        assert_eq!(spans.len(), 1);
+        assert_eq!((spans[0].start, spans[0].end), (at(0), at(25)));
+    }
+
+    #[test]
+    fn reads_up_to_the_last_representable_date() {
+        let (_temp, history) = temp_history("max-date", 0);
+        assert!(read(&history.dir, NaiveDate::MAX, NaiveDate::MAX).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,543 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/http_api.rs
+ */
+
+use crate::app_rules::{AppRules, RuleAction};
+use crate::auth::Secret;
+use crate::config::{HttpConfig, RulesConfig};
+use crate::control::Event;
+use crate::get_active_window::WindowInfo;
+use crate::history::{self, Span};
+use crate::metrics::{self, Metrics};
+use crate::status_window::AppStatus;
+use crate::time_report::local_midnight;
+use chrono::{Local, NaiveDate};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::warn;
+
+/// 请求行和请求头最多读取的字节数
+const MAX_HEADER_BYTES: u64 = 8192;
+/// 读取请求的超时时间
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+/// SSE 注释行的间隔，用来及时发现断开的客户端，也防止代理断开空闲连接
+const KEEPALIVE: std::time::Duration = std::time::Duration::from_secs(15);
+/// /history 一次最多查询的天数
+const MAX_HISTORY_DAYS: i64 = 366;
+
+/// run_loop 维护的共享数据
+pub struct Shared {
+    pub status: Arc<Mutex<AppStatus>>,
+    pub metrics: Arc<Mutex<Metrics>>,
+    pub events: broadcast::Sender<Event>,
+}
+
+/// 各个连接共享的数据
+struct State {
+    shared: Shared,
+    history_dir: PathBuf,
+    /// 全局规则，/history 与状态、事件一样只公开过滤后的内容
+    rules: AppRules,
+    exclude: Vec<String>,
+    token: Secret,
+    allow_origin: Option<String>,
+}
+
+/// 只解析用得到的部分：方法、路径、查询参数、Host 和 Authorization 头
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    host: Option<String>,
+    authorization: Option<String>,
+}
+
+impl Request {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// 启动 HTTP 接口，返回实际监听的地址和监听任务；abort 监听任务时所有连接一起关闭
+///
+/// 必须在 tokio 运行时中调用
+pub fn serve(
+    config: &HttpConfig,
+    history_dir: PathBuf,
+    rules: &RulesConfig,
+    shared: Shared,
+) -> Result<(SocketAddr, JoinHandle<()>), Box<dyn Error>> {
+    let exclude = rules.exclude.clone();
+    let rules = AppRules::compile(rules, &[], &[])?;
+    // 同步绑定，端口被占用时可以直接返回错误
+    let listener = std::net::TcpListener::bind(&config.bind)?;
+    listener.set_nonblocking(true)?;
+    let listener = tokio::net::TcpListener::from_std(listener)?;
+    let address = listener.local_addr()?;
+
+    let state = Arc::new(State {
+        shared,
+        history_dir,
+        rules,
+        exclude,
+        token: config.resolved_token.clone(),
+        allow_origin: config.allow_origin.clone(),
+    });
+    let handle = tokio::spawn(async move {
+        let mut connections = JoinSet::new();
+        loop {
+            tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok((stream, _)) => {
+                        let state = state.clone();
+                        connections.spawn(async move {
+                            if let Err(e) = handle_connection(stream, &state).await {
+                                warn!("HTTP connection failed: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => warn!("HTTP accept failed: {}", e),
+                },
+                // 回收已经结束的连接
+                Some(_) = connections.join_next() => {}
+            }
+        }
+    });
+
+    Ok((address, handle))
+}
+
+async fn handle_connection(mut stream: TcpStream, state: &State) -> Result<(), Box<dyn Error>> {
+    let local = stream.local_addr()?.ip();
+    let (reader, mut writer) = stream.split();
+    let request = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(reader)).await {
+        Ok(Ok(request)) => request,
+        Ok(Err(e)) => {
+            let body = json!({ "error": format!("bad request: {}", e) });
+            return respond(&mut writer, state, "400 Bad Request", &body).await;
+        }
+        // 客户端迟迟不发完请求，直接断开
+        Err(_) => return Ok(()),
+    };
+
+    if !allowed_host(request.host.as_deref(), local) {
+        let body = json!({ "error": "unexpected Host header" });
+        return respond(&mut writer, state, "403 Forbidden", &body).await;
+    }
+    // 浏览器带 Authorization 头跨域请求前的预检，不需要 token；只有配置了 allow_origin 时才允许跨域
+    if request.method == "OPTIONS" {
+        let mut head = head(state, "204 No Content", None);
+        if state.allow_origin.is_some() {
+            head += "Access-Control-Allow-Methods: GET\r\nAccess-Control-Allow-Headers: Authorization\r\n";
+        }
+        writer.write_all((head + "\r\n").as_bytes()).await?;
+        return Ok(());
+    }
+    if request.method != "GET" {
+        let body = json!({ "error": "only GET is supported" });
+        return respond(&mut writer, state, "405 Method Not Allowed", &body).await;
+    }
+    if !authorized(&request, &state.token) {
+        let body = json!({ "error": "missing or invalid token" });
+        return respond(&mut writer, state, "401 Unauthorized", &body).await;
+    }
+
+    match request.path.as_str() {
+        "/status" => {
+            let status = state.shared.status.lock().unwrap().clone();
+            respond(&mut writer, state, "200 OK", &serde_json::to_value(status)?).await
+        }
+        "/metrics" => {
+            let status = state.shared.status.lock().unwrap().clone();
+            let body = metrics::render(&status, &state.shared.metrics.lock().unwrap());
+            let response = head(state, "200 OK", Some("text/plain; version=0.0.4; charset=utf-8"))
+                + &format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+            writer.write_all(response.as_bytes()).await?;
+            Ok(())
+        }
+        "/history" => match history(&request, state) {
+            Ok(body) => respond(&mut writer, state, "200 OK", &body).await,
+            Err(e) => respond(&mut writer, state, "400 Bad Request", &json!({ "error": e })).await,
+        },
+        "/events" => stream_events(&mut writer, state).await,
+        _ => {
+            let body = json!({ "error": format!("unknown path {}", request.path) });
+            respond(&mut writer, state, "404 Not Found", &body).await
+        }
+    }
+}
+
+/// 读取请求行和请求头，GET 请求没有请求体
+async fn read_request(reader: impl AsyncRead + Unpin) -> Result<Request, String> {
+    let mut lines = BufReader::new(reader.take(MAX_HEADER_BYTES)).lines();
+    let request_line = lines
+        .next_line()
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("empty request")?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(format!("invalid request line '{}'", request_line));
+    };
+
+    let mut host = None;
+    let mut authorization = None;
+    loop {
+        let line = lines
+            .next_line()
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("request headers are too long")?;
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("host") {
+                host = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    Ok(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (percent_decode(key), percent_decode(value))
+            })
+            .collect(),
+        host,
+        authorization,
+    })
+}
+
+/// 防止 DNS 重绑定：网页把自己的域名解析到 127.0.0.1 后，浏览器发来的 Host 仍是这个域名。
+/// 只接受 localhost、回环地址和这个连接实际访问的地址；没有 Host 头的请求不是浏览器发出的
+fn allowed_host(host: Option<&str>, local: IpAddr) -> bool {
+    let Some(host) = host else {
+        return true;
+    };
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map(|(name, _)| name).unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    if name.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    name.parse::<IpAddr>()
+        .is_ok_and(|ip| ip.is_loopback() || ip.to_canonical() == local.to_canonical())
+}
+
+/// 没有设置 token 时都允许；否则需要 Authorization: Bearer <token>，
+/// 或者 ?token=<token>（浏览器的 EventSource 和 OBS 浏览器源不能设置请求头）
+fn authorized(request: &Request, token: &Secret) -> bool {
+    let expected = token.expose();
+    if expected.is_empty() {
+        return true;
+    }
+    let bearer = request
+        .authorization
+        .as_deref()
+        .and_then(|value| value.strip_prefix("Bearer "));
+    [bearer, request.param("token")]
+        .into_iter()
+        .flatten()
+        .any(|given| same(given.as_bytes(), expected.as_bytes()))
+}
+
+/// 比较全部字节，耗时与第几个字节不同无关
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// GET /history?from=YYYY-MM-DD&to=YYYY-MM-DD：与这几天（本地日期，都包含）有重叠的时间段，默认今天
+fn history(request: &Request, state: &State) -> Result<Value, String> {
+    let date = |name: &str| -> Result<Option<NaiveDate>, String> {
+        request
+            .param(name)
+            .map(|value| {
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map_err(|e| format!("{} expects YYYY-MM-DD, got '{}': {}", name, value, e))
+            })
+            .transpose()
+    };
+    let today = Local::now().date_naive();
+    let from = date("from")?.unwrap_or(today);
+    let to = date("to")?.unwrap_or(today.max(from));
+    if from > to {
+        return Err(format!("from {} is after to {}", from, to));
+    }
+    if (to - from).num_days() >= MAX_HISTORY_DAYS {
+        return Err(format!("at most {} days can be queried at once", MAX_HISTORY_DAYS));
+    }
+
+    let next_day = to.succ_opt().ok_or_else(|| format!("to {} is out of range", to))?;
+    let start = local_midnight(from);
+    let end = local_midnight(next_day);
+    let spans: Vec<Span> = history::read(&state.history_dir, from, to)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|span| span.end > start && span.start < end)
+        .filter_map(|span| public_span(state, span))
+        .collect();
+    Ok(json!({
+        "from": from.to_string(),
+        "to": to.to_string(),
+        "spans": spans,
+    }))
+}
+
+/// 本地历史记录的是原始窗口，公开前套用全局规则：被丢弃或在 exclude 中的应用去掉，
+/// 命中 label、busy 时只留下替换后的应用名
+fn public_span(state: &State, span: Span) -> Option<Span> {
+    if state.exclude.iter().any(|name| name.eq_ignore_ascii_case(&span.app)) {
+        return None;
+    }
+    let window = WindowInfo {
+        process: span.app.clone(),
+        class: span.class.clone(),
+        exe: span.exe.clone(),
+        title: span.title.clone(),
+    };
+    let (label, media) = match state.rules.check(&window) {
+        None => return Some(span),
+        Some((RuleAction::Drop, _)) => return None,
+        Some((RuleAction::Label(label), _)) => (label.clone(), span.media),
+        Some((RuleAction::Busy(label), _)) => (label.clone(), None),
+    };
+    Some(Span {
+        app: label,
+        class: String::new(),
+        exe: String::new(),
+        title: String::new(),
+        media,
+        ..span
+    })
+}
+
+/// GET /events：Server-Sent Events，先发送一次当前状态，之后推送事件（每次检测的 status 除外）
+async fn stream_events(
+    writer: &mut (impl AsyncWriteExt + Unpin),
+    state: &State,
+) -> Result<(), Box<dyn Error>> {
+    let mut receiver = state.shared.events.subscribe();
+    let head = head(state, "200 OK", Some("text/event-stream")) + "Cache-Control: no-cache\r\n\r\n";
+    writer.write_all(head.as_bytes()).await?;
+
+    // 客户端断开时写入失败，结束推送
+    let status = state.shared.status.lock().unwrap().clone();
+    let first = sse_event(&Event::Status { status: Box::new(status) })?;
+    if writer.write_all(first.as_bytes()).await.is_err() {
+        return Ok(());
+    }
+
+    let mut keepalive = tokio::time::interval(KEEPALIVE);
+    keepalive.tick().await;
+    loop {
+        let chunk = tokio::select! {
+            event = receiver.recv() => match event {
+                // 每次检测都会发送，太频繁，只推送变化
+                Ok(Event::Status { .. }) => continue,
+                Ok(event) => sse_event(&event)?,
+                // 客户端太慢，跳过错过的事件
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+            _ = keepalive.tick() => ": keepalive\n\n".to_string(),
+        };
+        if writer.write_all(chunk.as_bytes()).await.is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// 事件名取 event 字段，data 为完整的事件 JSON（单行）
+fn sse_event(event: &Event) -> Result<String, serde_json::Error> {
+    let data = serde_json::to_value(event)?;
+    let name = data["event"].as_str().unwrap_or("message");
+    Ok(format!("event: {}\ndata: {}\n\n", name, data))
+}
+
+/// 状态行和公共响应头，不含结尾的空行
+fn head(state: &State, status: &str, content_type: Option<&str>) -> String {
+    let mut head = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    if let Some(content_type) = content_type {
+        head += &format!("Content-Type: {}\r\n", content_type);
+    }
+    if let Some(origin) = &state.allow_origin {
+        head += &format!("Access-Control-Allow-Origin: {}\r\n", origin);
+    }
+    if status.starts_with("401") {
+        head += "WWW-Authenticate: Bearer\r\n";
+    }
+    head
+}
+
+async fn respond(
+    writer: &mut (impl AsyncWriteExt + Unpin),
+    state: &State,
+    status: &str,
+    body: &Value,
+) -> Result<(), Box<dyn Error>> {
+    let body = body.to_string();
+    let response = head(state, status, Some("application/json"))
+        + &format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+    writer.write_all(response.as_bytes()).await?;
+    Ok(())
+}
+
+/// 解码查询参数中的 %XX 和 +
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use chrono::Duration;
+
+    #[test]
+    fn accepts_loopback_and_local_hosts() {
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        for host in ["localhost:5650", "LOCALHOST", "127.0.0.1:5650", "[::1]:5650", "127.0.0.2"] {
+            assert!(allowed_host(Some(host), local), "{}", host);
+        }
+        assert!(allowed_host(None, local));
+
+        let lan: IpAddr = "::ffff:192.168.1.5".parse().unwrap();
+        assert!(allowed_host(Some("192.168.1.5:5650"), lan));
+    }
+
+    #[test]
+    fn rejects_rebound_hosts() {
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        for host in ["evil.example:5650", "localhost.evil.example", "192.168.1.5:5650", "", "[::1"] {
+            assert!(!allowed_host(Some(host), local), "{}", host);
+        }
+    }
+
+    #[test]
+    fn history_hides_filtered_apps() {
+        let dir = TempDir::new("http-history");
+        let date = NaiveDate::from_ymd_opt(2026, 1, 15).unwrap();
+        let noon = local_midnight(date) + Duration::hours(12);
+        let span = |app: &str, class: &str, title: &str, minute: i64| Span {
+            start: noon + Duration::minutes(minute),
+            end: noon + Duration::minutes(minute + 1),
+            app: app.to_string(),
+            class: class.to_string(),
+            exe: String::new(),
+            title: title.to_string(),
+            media: None,
+        };
+        let lines: Vec<String> = [
+            span("Code", "code", "main.rs", 0),
+            span("", "bank-app", "Account 1234", 1),
+            span("WeChat", "wechat", "Alice", 2),
+            span("Slack", "slack", "#hr-private", 3),
+        ]
+        .iter()
+        .map(|span| serde_json::to_string(span).unwrap())
+        .collect();
+        std::fs::write(dir.join("2026-01-15.jsonl"), lines.join("\n")).unwrap();
+
+        let rules: RulesConfig = toml::from_str(
+            r#"
+            exclude = ["WeChat"]
+
+            [[deny]]
+            class = "bank-app"
+
+            [[deny]]
+            class = "slack"
+            action = "busy"
+            "#,
+        )
+        .unwrap();
+        let state = history_state(dir.path().to_path_buf(), &rules);
+
+        let body = history(&history_request("2026-01-15"), &state).unwrap().to_string();
+        assert!(body.contains("main.rs"));
+        for hidden in ["bank-app", "Account 1234", "WeChat", "Alice", "Slack", "slack", "#hr-private"] {
+            assert!(!body.contains(hidden), "{} leaked: {}", hidden, body);
+        }
+        assert!(body.contains(&format!("\"app\":\"{}\"", rules.busy_label)));
+    }
+
+    #[test]
+    fn rejects_history_past_the_last_date() {
+        let state = history_state(std::env::temp_dir(), &RulesConfig::default());
+        let last = NaiveDate::MAX.format("%Y-%m-%d").to_string();
+        let error = history(&history_request(&last), &state).unwrap_err();
+        assert!(error.contains("out of range"), "{}", error);
+    }
+
+    fn history_state(history_dir: PathBuf, rules: &RulesConfig) -> State {
+        State {
+            shared: Shared {
+                status: Default::default(),
+                metrics: Default::default(),
+                events: broadcast::channel(1).0,
+            },
+            history_dir,
+            rules: AppRules::compile(rules, &[], &[]).unwrap(),
+            exclude: rules.exclude.clone(),
+            token: Secret::default(),
+            allow_origin: None,
+        }
+    }
+
+    fn history_request(from: &str) -> Request {
+        Request {
+            method: "GET".to_string(),
+            path: "/history".to_string(),
+            query: vec![("from".to_string(), from.to_string())],
+            host: None,
+            authorization: None,
+        }
+    }
+}
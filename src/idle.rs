@@ -0,0 +1,52 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/idle.rs
+ */
+
+use dbus::blocking::Connection;
+use std::process::Command;
+use std::time::Duration;
+
+const DBUS_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// X11 下用 xprintidle 读取空闲时间（毫秒）
+fn xprintidle() -> Option<Duration> {
+    let output = Command::new("xprintidle").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let millis: u64 = String::from_utf8_lossy(&output.stdout).trim().parse().ok()?;
+    Some(Duration::from_millis(millis))
+}
+
+/// GNOME（X11 和 Wayland）：org.gnome.Mutter.IdleMonitor，单位毫秒
+fn mutter_idle(connection: &Connection) -> Option<Duration> {
+    let proxy = connection.with_proxy(
+        "org.gnome.Mutter.IdleMonitor",
+        "/org/gnome/Mutter/IdleMonitor/Core",
+        DBUS_TIMEOUT,
+    );
+    let (millis,): (u64,) = proxy
+        .method_call("org.gnome.Mutter.IdleMonitor", "GetIdletime", ())
+        .ok()?;
+    Some(Duration::from_millis(millis))
+}
+
+/// KDE 等实现了 org.freedesktop.ScreenSaver 的桌面，单位毫秒
+fn screensaver_idle(connection: &Connection) -> Option<Duration> {
+    let proxy = connection.with_proxy("org.freedesktop.ScreenSaver", "/ScreenSaver", DBUS_TIMEOUT);
+    let (millis,): (u32,) = proxy
+        .method_call("org.freedesktop.ScreenSaver", "GetSessionIdleTime", ())
+        .ok()?;
+    Some(Duration::from_millis(millis as u64))
+}
+
+/// 距离上一次键盘鼠标输入的时间，所有方法都不可用时返回 None
+pub fn idle_time() -> Option<Duration> {
+    if let Some(idle) = xprintidle() {
+        return Some(idle);
+    }
+    let connection = Connection::new_session().ok()?;
+    mutter_idle(&connection).or_else(|| screensaver_idle(&connection))
+}
@@ -0,0 +1,66 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/instance.rs
+ */
+
+use crate::app_dirs;
+use crate::control::{self, Request};
+use std::error::Error;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 锁文件位置：$XDG_RUNTIME_DIR/processforlinux/instance.lock，内容为持有者的 PID
+pub fn lock_path() -> PathBuf {
+    app_dirs::runtime_dir().join("instance.lock")
+}
+
+/// 单实例锁，进程退出时由系统释放，异常退出也不会留下死锁
+pub struct InstanceLock {
+    _file: File,
+}
+
+/// 尝试成为唯一的实例；已有实例在运行时返回它的 PID（读不到时为 None）
+pub fn acquire() -> Result<Result<InstanceLock, Option<u32>>, Box<dyn Error>> {
+    let path = lock_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+    match file.try_lock() {
+        Ok(()) => {
+            file.set_len(0)?;
+            file.rewind()?;
+            write!(file, "{}", std::process::id())?;
+            Ok(Ok(InstanceLock { _file: file }))
+        }
+        Err(TryLockError::WouldBlock) => {
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            Ok(Err(pid.trim().parse().ok()))
+        }
+        Err(TryLockError::Error(e)) => Err(format!("Failed to lock {}: {}", path.display(), e).into()),
+    }
+}
+
+/// 再次启动时把请求转给正在运行的实例；它可能刚启动，控制套接字还没就绪，稍等重试
+pub fn forward(request: &Request) -> Result<(), Box<dyn Error>> {
+    let mut attempts = 0;
+    loop {
+        match control::send(request) {
+            Ok(_) => return Ok(()),
+            Err(_) if attempts < 10 && !control::socket_path().exists() => {
+                attempts += 1;
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/log_file.rs
+ */
+
+use crate::config::LogConfig;
+use chrono::{DateTime, Local};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+const FILE_NAME: &str = "processforlinux.log";
+
+/// 按时间和大小切分的日志文件
+///
+/// 当前日志写入 processforlinux.log，切分时改名为 processforlinux.<时间>.log，
+/// 只保留最新的 keep 个旧文件
+pub struct RollingFile {
+    dir: PathBuf,
+    file: File,
+    size: u64,
+    /// 当前文件所属的周期，例如 2026-10-18；rotation 为 never 时为空
+    period: String,
+    rotation: String,
+    max_size: u64,
+    keep: usize,
+}
+
+impl RollingFile {
+    pub fn open(config: &LogConfig) -> io::Result<Self> {
+        let dir = config.dir();
+        fs::create_dir_all(&dir)?;
+        let path = dir.join(FILE_NAME);
+        let file = open(&path)?;
+        let metadata = file.metadata()?;
+        // 已有的文件按最后修改时间计算周期，上次运行留下的旧日志在第一次写入时切分
+        let modified = metadata.modified().map(DateTime::<Local>::from).unwrap_or_else(|_| Local::now());
+        Ok(RollingFile {
+            period: period(&config.rotation, modified),
+            dir,
+            file,
+            size: metadata.len(),
+            rotation: config.rotation.clone(),
+            max_size: config.max_size * 1024 * 1024,
+            keep: config.keep,
+        })
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(FILE_NAME)
+    }
+
+    fn should_rotate(&self, now: DateTime<Local>, len: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        period(&self.rotation, now) != self.period || (self.max_size > 0 && self.size + len as u64 > self.max_size)
+    }
+
+    fn rotate(&mut self, now: DateTime<Local>) -> io::Result<()> {
+        self.file.flush()?;
+        let stamp = now.format("%Y%m%d-%H%M%S");
+        let mut rotated = self.dir.join(format!("processforlinux.{}.log", stamp));
+        // 同一秒内多次按大小切分时加序号
+        let mut index = 1;
+        while rotated.exists() {
+            rotated = self.dir.join(format!("processforlinux.{}-{}.log", stamp, index));
+            index += 1;
+        }
+        fs::rename(self.path(), &rotated)?;
+        self.file = open(&self.path())?;
+        self.size = 0;
+        self.period = period(&self.rotation, now);
+        self.prune();
+        Ok(())
+    }
+
+    /// 删除超出 keep 个数的旧日志，文件名中的时间可以直接按字典序排序
+    fn prune(&self) {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return;
+        };
+        let mut rotated: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+                name != FILE_NAME && name.starts_with("processforlinux.") && name.ends_with(".log")
+            })
+            .collect();
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.keep);
+        for path in &rotated[..excess] {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl Write for RollingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let now = Local::now();
+        if self.should_rotate(now, buf.len()) {
+            // 切分失败时继续写入当前文件，不能丢日志
+            if let Err(e) = self.rotate(now) {
+                let _ = writeln!(self.file, "Failed to rotate log file: {}", e);
+                self.period = period(&self.rotation, now);
+            }
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn period(rotation: &str, time: DateTime<Local>) -> String {
+    match rotation {
+        "hourly" => time.format("%Y-%m-%d %H").to_string(),
+        "daily" => time.format("%Y-%m-%d").to_string(),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,128 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/logging.rs
+ */
+
+use crate::config::{Config, LogConfig};
+use crate::log_file::RollingFile;
+use std::io::IsTerminal;
+use std::os::unix::fs::MetadataExt;
+use std::sync::{Mutex, OnceLock};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Layer, Registry};
+
+type Filtered = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type Output = Box<dyn Layer<Filtered> + Send + Sync>;
+
+/// 运行中修改日志级别用的句柄
+static FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
+/// RUST_LOG 优先，其次是配置中的 log.level
+fn filter(level: &str) -> EnvFilter {
+    EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level))
+}
+
+fn fmt_layer<W>(format: &str, ansi: bool, writer: W) -> Output
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    if format == "json" {
+        fmt::layer()
+            .json()
+            .with_current_span(false)
+            .with_span_list(true)
+            .with_writer(writer)
+            .boxed()
+    } else {
+        fmt::layer().with_ansi(ansi).with_writer(writer).boxed()
+    }
+}
+
+fn stderr_layer(format: &str) -> Output {
+    // 重定向到文件时不输出颜色
+    fmt_layer(format, std::io::stderr().is_terminal(), std::io::stderr)
+}
+
+/// systemd 把标准错误接到 journal 时会设置 JOURNAL_STREAM=<设备>:<inode>；
+/// 这个变量会被子进程继承，所以还要确认标准错误确实是这个流
+fn stderr_is_journal() -> bool {
+    let Some(stream) = std::env::var("JOURNAL_STREAM").ok() else {
+        return false;
+    };
+    let Ok(metadata) = std::fs::metadata("/proc/self/fd/2") else {
+        return false;
+    };
+    stream == format!("{}:{}", metadata.dev(), metadata.ino())
+}
+
+fn resolve_output(config: &LogConfig) -> &str {
+    match config.output.as_str() {
+        "auto" if stderr_is_journal() => "journald",
+        "auto" => "stderr",
+        output => output,
+    }
+}
+
+/// 按 log.output 创建输出，失败时退回标准错误并返回原因
+fn output_layer(config: &LogConfig) -> (Output, Option<String>) {
+    match resolve_output(config) {
+        "file" => match RollingFile::open(config) {
+            Ok(file) => (fmt_layer(&config.format, false, Mutex::new(file)), None),
+            Err(e) => (
+                stderr_layer(&config.format),
+                Some(format!("Failed to open log file in {}: {}, logging to stderr", config.dir().display(), e)),
+            ),
+        },
+        "journald" => match tracing_journald::layer() {
+            Ok(layer) => (layer.with_syslog_identifier("processforlinux".to_string()).boxed(), None),
+            Err(e) => (
+                stderr_layer(&config.format),
+                Some(format!("Failed to connect to journald: {}, logging to stderr", e)),
+            ),
+        },
+        _ => (stderr_layer(&config.format), None),
+    }
+}
+
+/// 初始化日志，默认输出到标准错误，标准输出留给 stdout 上报目标和子命令的输出
+///
+/// 没有配置时（子命令）只向标准错误输出警告和错误
+pub fn init(config: Option<&Config>) {
+    let (level, (output, fallback)) = match config {
+        Some(config) => (config.log_level(), output_layer(&config.log)),
+        None => ("warn", (stderr_layer("text"), None)),
+    };
+    let (filter, handle) = reload::Layer::new(filter(level));
+    if tracing_subscriber::registry().with(filter).with(output).try_init().is_ok() {
+        let _ = FILTER.set(handle);
+    }
+    if let Some(fallback) = fallback {
+        tracing::warn!("{}", fallback);
+    }
+}
+
+/// 重新加载配置后应用新的日志级别，输出位置和格式需要重启才能生效
+pub fn reload(previous: &Config, config: &Config) {
+    let (old, new) = (&previous.log, &config.log);
+    if old.format != new.format
+        || old.output != new.output
+        || old.dir() != new.dir()
+        || old.rotation != new.rotation
+        || old.max_size != new.max_size
+        || old.keep != new.keep
+    {
+        tracing::warn!("Log output settings changed, restart to apply them");
+    }
+    if previous.log_level() == config.log_level() {
+        return;
+    }
+    if let Some(handle) = FILTER.get() {
+        match handle.reload(filter(config.log_level())) {
+            Ok(()) => tracing::info!(level = config.log_level(), "Log level changed"),
+            Err(e) => tracing::warn!("Failed to change log level: {}", e),
+        }
+    }
+}
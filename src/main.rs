@@ -5,93 +5,118 @@
  * @LastEditTime: 2023-12-11 17:33:31
  * @FilePath: /processforlinux/src/main.rs
  */
+mod activity;
+mod activitywatch;
+mod app_dirs;
+mod app_rules;
+mod auth;
+mod autostart;
+mod config;
+mod config_watcher;
+mod control;
+mod doctor;
+mod extend;
 mod get_active_window;
 mod get_env_file;
 mod get_media;
+mod history;
+mod http_api;
+mod idle;
+mod instance;
+mod log_file;
+mod logging;
+mod metrics;
+mod offline_queue;
+mod pause;
+mod reporter;
 mod reportprocess;
+mod schedule;
+mod secret_store;
 mod status_window;
+mod systemd;
+mod target;
+mod template;
+#[cfg(test)]
+mod test_util;
+mod time_report;
 
 use chrono::Utc;
+use clap::ArgMatches;
 
+use offline_queue::OfflineQueue;
+use activity::Activity;
+use activitywatch::ActivityWatch;
+use app_rules::AppRules;
+use auth::Auth;
+use futures::future::join_all;
+use config::{Config, TargetConfig};
+use extend::{ExtendContext, ExtendTexts};
+use history::History;
+use reporter::{
+    JsonLinesReporter, MixSpaceReporter, Reporter, StdoutReporter, UnixSocketReporter,
+    WebhookReporter,
+};
+use pause::PauseState;
+use target::Target;
+use tokio::signal::unix::{signal, Signal, SignalKind};
 use std::process::exit;
-use std::{error::Error, time::Duration};
-use tokio::time::sleep;
-use std::collections::HashMap;
-use std::sync::mpsc;
+use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
-struct Config {
-    api_url: String,
-    api_key: String,
-    watch_time: i64,
-    media_enable: bool,
-    log_enable: bool,
-    gui_enable: bool,
-}
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            api_url: String::new(),
-            api_key: String::new(),
-            watch_time: 5,
-            media_enable: true,
-            log_enable: true,
-            gui_enable: true, // 默认启用GUI
-        }
-    }
-}
+async fn run_loop(
+    mut config: Config,
+    matches: ArgMatches,
+    overlay: Option<status_window::OverlayHandle>,
+    (control_sender, mut control_receiver): (control::ControlSender, control::ControlReceiver),
+) {
+    let mut watcher = config_watcher::watch(matches);
+    // 最近一次重新加载失败的原因，加载成功后清除
+    let mut config_error: Option<String> = None;
+    let mut pause = PauseState::default();
+    // SIGUSR1 切换暂停状态；Ctrl+C、SIGTERM（注销、systemctl stop）和关闭悬浮窗时退出
+    let mut toggle_signal = listen(SignalKind::user_defined1(), "SIGUSR1");
+    let mut interrupt_signal = listen(SignalKind::interrupt(), "SIGINT");
+    let mut terminate_signal = listen(SignalKind::terminate(), "SIGTERM");
+    let mut overlay_closed = overlay.as_ref().map(|overlay| overlay.closed.clone());
+    // 控制套接字请求的立即上报，上报完成后回复
+    let mut report_replies: Vec<tokio::sync::oneshot::Sender<Result<(), String>>> = Vec::new();
 
-// 根据进程名称获取扩展信息的函数
-fn get_extend_info(process_name: &str) -> String {
-    let mut extend_map = HashMap::new();
-
-    // 添加进程名称到扩展信息的映射（匹配get_active_window.rs中的枚举值）
-    extend_map.insert("idea", "要么享受着kt的爽，要么就是面向Spring开发中");
-    extend_map.insert("clion", "不会有人不喜欢C++吧？ 唉依赖，也是念起CMake vcpkg conan的好了");
-    extend_map.insert("code", "ESLint和Prettier天天在我的配置文件里打架");
-    extend_map.insert("firefox", "CSS调试唯一指定亲爹，但产品经理的电脑上没有它");
-    extend_map.insert("chrome", "Lighthouse跑分专用浏览器，只要关掉插件，我的网站就天下第一");
-    extend_map.insert("iterm2", "美化半天，结果99%的时间都在看 `npm install` 的进度条");
-    extend_map.insert("webstorm", "自动导入一时爽，索引项目火葬场，专治各种 'any' 写法");
-    extend_map.insert("pycharm", "后端同事的快乐老家，据说那里的缩进能决定项目死活");
-    extend_map.insert("goland", "新潮后端们的圣杯，据说能用interface{}写出JavaScript的感觉");
-    extend_map.insert("rustrover", "类型安全 无畏并发 Cargo 启动，编译慢到让人发指");
-    extend_map.insert("discord", "React/Vue/Svelte 官方指定撕逼广场");
-    extend_map.insert("spotify", "专注码字BGM生成器，一首歌的时间刚好够我命名一个CSS class");
-    extend_map.insert("telegram", "Vite作者的日常茶馆，前端前沿资讯的第一手信源（如果你看得懂）");
-    extend_map.insert("wechat", "前端兼容性噩梦的始作俑者，梦回IE6");
-    extend_map.insert("qq", "内置浏览器比微信还离谱，上古前端技术展览馆");
-    extend_map.insert("slack", "代码截图和部署机器人专用公告栏，以及Giphy斗图大赛主场");
-    extend_map.insert("typora", "写README.md的唯一动力，毕竟它排版比我写的UI好看多了");
-    extend_map.insert("vlc", "用来播放网上下载的付费教程，2倍速是基本操作");
-    extend_map.insert("obs", "录制 Bug 复现视频专用，顺便幻想自己是 live-coding 大神");
-    extend_map.insert("thunderbird", "GitHub和Vercel的通知轰炸区，专门用来接收构建失败的噩耗");
-    extend_map.insert("kmail", "GitHub和Vercel的通知轰炸区 II：The Sequel");
-    extend_map.insert("qqmusic", "当我的Babel编译卡住时，唯一能抚慰我心灵的东西");
-    extend_map.insert("music", "修复IE兼容性问题时的专用BGM播放器，评论区里都是同道中人");
-    extend_map.insert("yesplaymusic", "用Electron包装的听歌神器，充分体现了前端'万物皆可JS'的黑客精神");
-    extend_map.insert("studio", "Gradle syncing... @OptIn(Experimental::class)");
-    extend_map.insert("sublime_text", "上古前端大神们的信仰，打开速度比我的HMR（热更新）还快");
-    extend_map.insert("atom", "Electron的亲儿子，VSCode的探路石，前端圈的活化石");
-    extend_map.insert("libreoffice", "当产品经理发来一个.odt格式的需求文档时，我的内心就和打开它的样式一样崩溃");
-
-    // 检查进程名称中是否包含映射的关键词（不区分大小写）
-    let process_lower = process_name.to_lowercase();
-    for (key, value) in extend_map.iter() {
-        if process_lower.contains(key) {
-            return value.to_string();
+    // 控制套接字：status 读取共享的最新状态，subscribe 订阅事件流，其他请求交给本循环处理
+    let shared_status = Arc::new(Mutex::new(status_window::AppStatus::default()));
+    let shared_metrics = Arc::new(Mutex::new(metrics::Metrics::default()));
+    let (event_sender, _) = tokio::sync::broadcast::channel(64);
+    let control_path = match control::serve(shared_status.clone(), control_sender, event_sender.clone()) {
+        Ok(path) => {
+            info!("Control socket listening on {}", path.display());
+            Some(path)
         }
-    }
-
-    // 如果没有找到匹配的，返回空字符串
-    String::new()
-}
-
-async fn run_loop(config: Config, status_sender: Option<mpsc::Sender<status_window::AppStatus>>) {
+        Err(e) => {
+            error!("Failed to start control socket: {}", e);
+            None
+        }
+    };
+    let mut http_server = start_http(&config, &shared_status, &shared_metrics, &event_sender);
+    let mut notifier = systemd::Notifier::new();
+    notifier.ready();
     let mut last_time = Utc::now();
     let mut previous_process_name = String::new();
     let mut previous_media_metadata: get_media::MediaMetadata = get_media::MediaMetadata::default();
 
+    let mut targets = build_targets(&config);
+    let mut extend_texts = ExtendTexts::compile(&config.extend).unwrap_or_default();
+    let mut global_rules = AppRules::compile(&config.rules, &[], &[]).unwrap_or_default();
+    // 当前应用（按窗口类名区分）和切换到它的时间，用于 {duration}
+    let mut current_app = String::new();
+    let mut app_since = Utc::now();
+    let mut history = open_history(&config);
+    let mut activitywatch = open_activitywatch(&config);
+    // 最近一次通知过的前台窗口和媒体，用于 focus / media 事件
+    let mut last_focus: Option<get_active_window::WindowInfo> = None;
+    let mut last_media: Option<(String, String)> = None;
+
     // 初始化统计信息
     let mut stats = status_window::RunningStats {
         start_time: Utc::now(),
@@ -102,160 +127,874 @@ async fn run_loop(config: Config, status_sender: Option<mpsc::Sender<status_wind
 
     loop {
         let utc_now = Utc::now();
+        if let Some(event) = pause.expire(utc_now) {
+            // 恢复后立即上报当前状态
+            previous_process_name.clear();
+            let _ = event_sender.send(event);
+        }
+        // 每轮检测一个 span，上报的日志都带上 cycle
+        let check_span = info_span!("check", cycle = stats.total_checks + 1);
         let next_check_time = utc_now
             .checked_add_signed(chrono::Duration::seconds(config.watch_time))
             .unwrap_or(utc_now);
 
-        let media_metadata = if config.media_enable {
-            get_media::get_media_metadata().unwrap_or_default()
+        let media_metadata = if config.media.enable {
+            get_media::get_media_metadata(&config.media.players).unwrap_or_default()
         } else {
             get_media::MediaMetadata::default()
         };
 
-        let (process_name, window_status) = match get_active_window::get_active_window_process_and_title() {
-            Ok(name) => {
+        let detection_started = std::time::Instant::now();
+        let detected = get_active_window::get_active_window_info();
+        let detection_time = detection_started.elapsed();
+        let (window, window_status) = match detected {
+            Ok(window) => {
                 stats.success_count += 1;
-                (name, status_window::WindowStatus::Success)
+                (window, status_window::WindowStatus::Success)
             }
             Err(e) => {
                 stats.failure_count += 1;
-                eprintln!("Failed to get active window: {}", e);
-                (String::new(), status_window::WindowStatus::Failed(e.to_string()))
+                check_span.in_scope(|| warn!("Failed to get active window: {}", e));
+                (
+                    get_active_window::WindowInfo::default(),
+                    status_window::WindowStatus::Failed(e.to_string()),
+                )
             }
         };
+        let process_name = window.process.clone();
+        if window.class != current_app {
+            current_app = window.class.clone();
+            app_since = utc_now;
+        }
+        // 暂停时按隐私设置改为占位上报，或者完全不上报
+        let (process_name, media_metadata, extend_info, window) = if pause.is_paused() {
+            (
+                config.privacy.process.clone(),
+                None,
+                config.privacy.extend.clone(),
+                None,
+            )
+        } else {
+            let extend_info = extend_texts.render(&ExtendContext {
+                window: &window,
+                media: Some(&media_metadata),
+                duration: utc_now - app_since,
+            });
+            (process_name, Some(media_metadata), extend_info, Some(window))
+        };
+        // 始终发送原始的进程名称，extend 字段独立存在
+        let activity = Activity {
+            process: process_name.clone(),
+            extend: extend_info,
+            media: media_metadata.clone(),
+            timestamp: utc_now.timestamp(),
+            window,
+        };
+        // 状态、事件和指标对所有订阅者公开，只能包含过滤后的内容
+        let public = public_activity(&config, &global_rules, &activity);
+        let public_media = public
+            .as_ref()
+            .and_then(|public| public.media.clone())
+            .unwrap_or_default();
 
-        stats.total_checks += 1;
+        // 本地历史和 ActivityWatch 也只记录过滤后的窗口；暂停期间和被规则丢弃的应用不记录，
+        // 命中 label、busy 时只有替换后的应用名，排除的应用没有应用名，同样不记录
+        let recorded = public
+            .as_ref()
+            .filter(|_| !pause.is_paused())
+            .map(public_window);
+        if let Some(history) = history.as_mut() {
+            history.observe(utc_now, recorded.as_ref(), Some(&public_media));
+        }
+        if let Some(activitywatch) = activitywatch.as_mut() {
+            // 读取空闲时间可能会访问 D-Bus，放到阻塞线程里
+            let idle = tokio::task::spawn_blocking(idle::idle_time).await.ok().flatten();
+            activitywatch.heartbeat(utc_now, recorded.as_ref(), idle).await;
+        }
 
-        // 发送状态更新到GUI
-        if let Some(ref sender) = status_sender {
-            let session_type = get_active_window::detect_session_type();
-            let app_status = status_window::AppStatus {
-                session_type,
-                current_window: process_name.clone(),
-                window_status: window_status.clone(),
-                next_check_time,
-                watch_interval: config.watch_time,
-                media_title: media_metadata.title.clone().unwrap_or_default(),
-                media_artist: media_metadata.artist.clone().unwrap_or_default(),
-                media_thumbnail: media_metadata.thumbnail.clone().unwrap_or_default(),
-                stats: stats.clone(),
-                last_error: match &window_status {
-                    status_window::WindowStatus::Failed(err) => Some(err.clone()),
-                    _ => None,
-                },
+        // 前台窗口和媒体变化时通知订阅者；暂停期间和被规则丢弃的应用不通知，恢复后重新发送
+        match public.as_ref().filter(|_| !pause.is_paused()) {
+            Some(public) => {
+                let focus = public_window(public);
+                if last_focus.as_ref() != Some(&focus) {
+                    let _ = event_sender.send(control::Event::Focus {
+                        process: focus.process.clone(),
+                        class: focus.class.clone(),
+                        title: focus.title.clone(),
+                    });
+                    last_focus = Some(focus);
+                }
+                let media = (
+                    public_media.title.clone().unwrap_or_default(),
+                    public_media.artist.clone().unwrap_or_default(),
+                );
+                if last_media.as_ref() != Some(&media) {
+                    let _ = event_sender.send(control::Event::Media {
+                        title: media.0.clone(),
+                        artist: media.1.clone(),
+                    });
+                    last_media = Some(media);
+                }
+            }
+            None => {
+                last_focus = None;
+                last_media = None;
+            }
+        }
+
+        stats.total_checks += 1;
+        {
+            let mut metrics = shared_metrics.lock().unwrap();
+            metrics.detection.observe(detection_time.as_secs_f64());
+            metrics.last_check = Some(utc_now);
+            (metrics.app, metrics.class) = match &public {
+                Some(public) => {
+                    let window = public_window(public);
+                    (window.process, window.class)
+                }
+                None => (String::new(), String::new()),
             };
-            let _ = sender.send(app_status);
         }
 
-        let prev_process_name = previous_process_name.clone();
-        let prev_media_metadata = previous_media_metadata.clone();
+        // 更新状态，发送到GUI和控制套接字
+        let app_status = status_window::AppStatus {
+            session_type: get_active_window::detect_session_type(),
+            current_window: public.as_ref().map(|public| public.process.clone()).unwrap_or_default(),
+            window_status: window_status.clone(),
+            next_check_time,
+            watch_interval: config.watch_time,
+            media_title: public_media.title.clone().unwrap_or_default(),
+            media_artist: public_media.artist.clone().unwrap_or_default(),
+            media_thumbnail: public_media.thumbnail.clone().unwrap_or_default(),
+            stats: stats.clone(),
+            targets: targets.iter().map(|target| target.health.clone()).collect(),
+            config_error: config_error.clone(),
+            paused: pause.is_paused(),
+            paused_until: pause.until(),
+            last_error: match &window_status {
+                status_window::WindowStatus::Failed(err) => Some(err.clone()),
+                _ => None,
+            },
+        };
+        notifier.status(match &app_status.window_status {
+            _ if app_status.paused => "Reporting paused".to_string(),
+            status_window::WindowStatus::Failed(e) => format!("Failed to get active window: {}", e),
+            _ => format!("Current app: {}", app_status.current_window),
+        });
+        notifier.watchdog();
+        *shared_status.lock().unwrap() = app_status.clone();
+        let _ = event_sender.send(control::Event::Status { status: Box::new(app_status.clone()) });
+        if let Some(ref overlay) = overlay {
+            let _ = overlay.status.send(app_status);
+        }
 
-        if prev_process_name != process_name
-            || prev_media_metadata != media_metadata
+        let media_changed = match &media_metadata {
+            Some(media_metadata) => *media_metadata != previous_media_metadata,
+            None => false,
+        };
+
+        if pause.is_paused() && config.privacy.mode == "silent" {
+            check_span.in_scope(|| debug!("Reporting paused, skipping report"));
+            for reply in report_replies.drain(..) {
+                let _ = reply.send(Err("reporting is paused".to_string()));
+            }
+        } else if !report_replies.is_empty()
+            || targets.iter().any(|target| target.schedule_changed(utc_now))
+            || previous_process_name != process_name
+            || media_changed
             || (utc_now - last_time).num_seconds() > 20
         {
-            let extend_info = get_extend_info(&process_name);
-
-            // 添加调试信息
-            println!("DEBUG: 检测到的进程名称: '{}'", process_name);
-            println!("DEBUG: 扩展信息: '{}'", extend_info);
-
-            // 始终发送原始的进程名称，extend 字段独立存在
-            if let Err(e) = report(
-                &process_name,  // 直接发送原始进程名称
-                &media_metadata.title.clone().unwrap_or_default(),
-                &media_metadata.artist.clone().unwrap_or_default(),
-                &media_metadata.thumbnail.clone().unwrap_or_default(),
-                &extend_info,   // extend 字段独立，不影响 process
-                &config,
-            )
-            .await
-            {
-                eprintln!("Failed to report: {}", e);
+            check_span.in_scope(|| debug!(process = %process_name, extend = %activity.extend, "Reporting activity"));
+
+            // 各目标并发上报，互不阻塞
+            // 上报卡住时也要能及时退出，未完成的上报直接放弃
+            let reports = join_all(targets.iter_mut().map(|target| target.report(&activity))).instrument(check_span);
+            let interrupted = tokio::select! {
+                _ = reports => None,
+                Some(()) = recv(&mut interrupt_signal) => Some("SIGINT"),
+                Some(()) = recv(&mut terminate_signal) => Some("SIGTERM"),
+            };
+            if let Some(name) = interrupted {
+                info!("Received {}, shutting down", name);
+                break;
+            }
+            shared_status.lock().unwrap().targets = targets.iter().map(|target| target.health.clone()).collect();
+            shared_metrics.lock().unwrap().targets = targets
+                .iter()
+                .map(|target| (target.name().to_string(), target.metrics.clone()))
+                .collect();
+            let _ = event_sender.send(control::Event::Report {
+                activity: public.as_ref().map(Activity::to_json).unwrap_or_default(),
+                targets: targets.iter().map(|target| target.health.clone()).collect(),
+            });
+            for reply in report_replies.drain(..) {
+                let _ = reply.send(Ok(()));
             }
 
             previous_process_name = process_name;
-            previous_media_metadata = media_metadata;
+            previous_media_metadata = media_metadata.unwrap_or_default();
             last_time = utc_now;
-        } else if config.log_enable {
-            let next_watch_time = utc_now
-                .checked_add_signed(chrono::Duration::seconds(config.watch_time))
-                .unwrap()
-                .format("%Y-%m-%d %H:%M:%S");
-            let utc_now = utc_now.format("%Y-%m-%d %H:%M:%S");
-            println!("--------------------------------------------------");
-            println!("This Watch Time : {}", utc_now);
-            println!("No change in process or media metadata");
-            println!("Next Watch Time : {}", next_watch_time);
-            println!("--------------------------------------------------");
+        } else {
+            check_span.in_scope(|| debug!(next = %next_check_time.format("%H:%M:%S"), "No change in process or media metadata"));
         }
         let sleep_interval_secs = config.watch_time.to_string().parse::<u64>().unwrap_or(5);
-        sleep(Duration::from_secs(sleep_interval_secs)).await;
+        tokio::select! {
+            _ = notifier.sleep(Duration::from_secs(sleep_interval_secs)) => {}
+            Some(reload) = watcher.next() => {
+                let result = match reload.result {
+                    Ok(new_config) => {
+                        if new_config.gui.enable != config.gui.enable {
+                            warn!("gui.enable changed, restart to apply it");
+                        }
+                        // 新配置已经校验过，重建目标后整体替换；同名目标保留统计，上报方式没变时还保留退避和时间表状态
+                        let mut new_targets = build_targets(&new_config);
+                        for target in &mut new_targets {
+                            if let Some(previous) = targets.iter().find(|previous| previous.name() == target.name()) {
+                                target.carry_over(previous);
+                            }
+                        }
+                        targets = new_targets;
+                        extend_texts = ExtendTexts::compile(&new_config.extend).unwrap_or_default();
+                        global_rules = AppRules::compile(&new_config.rules, &[], &[]).unwrap_or_default();
+                        if new_config.history != config.history || new_config.watch_time != config.watch_time {
+                            if let Some(history) = history.as_mut() {
+                                history.finish(Utc::now());
+                            }
+                            history = open_history(&new_config);
+                        }
+                        if new_config.activitywatch != config.activitywatch || new_config.watch_time != config.watch_time {
+                            activitywatch = open_activitywatch(&new_config);
+                        }
+                        if new_config.http != config.http
+                            || new_config.history.dir() != config.history.dir()
+                            || new_config.rules != config.rules
+                        {
+                            // 等旧的监听任务结束、端口释放后再重新监听
+                            if let Some(server) = http_server.take() {
+                                server.abort();
+                                let _ = server.await;
+                            }
+                            http_server = start_http(&new_config, &shared_status, &shared_metrics, &event_sender);
+                        }
+                        logging::reload(&config, &new_config);
+                        config = new_config;
+                        config_error = None;
+                        // 下一轮立即按新配置检查并上报
+                        previous_process_name.clear();
+                        info!("Config reloaded");
+                        let _ = event_sender.send(control::Event::ConfigReloaded);
+                        Ok(())
+                    }
+                    Err(e) => {
+                        error!("Failed to reload config, keeping the previous one: {}", e);
+                        config_error = Some(e.clone());
+                        let _ = event_sender.send(control::Event::ConfigError { error: e.clone() });
+                        Err(e)
+                    }
+                };
+                if let Some(reply) = reload.reply {
+                    let _ = reply.send(result);
+                }
+            }
+            Some(message) = control_receiver.recv() => {
+                match message.request {
+                    control::Request::Pause { minutes } => {
+                        let result = pause.pause(minutes).map(|event| {
+                            let _ = event_sender.send(event);
+                        });
+                        let _ = message.reply.send(result);
+                    }
+                    control::Request::Resume => {
+                        previous_process_name.clear();
+                        let _ = event_sender.send(pause.resume());
+                        let _ = message.reply.send(Ok(()));
+                    }
+                    // 下一轮立即检测并上报，上报完成后回复
+                    control::Request::Report => report_replies.push(message.reply),
+                    control::Request::Reload => watcher.reload(message.reply),
+                    control::Request::ShowOverlay => {
+                        let result = match &overlay {
+                            Some(overlay) => {
+                                overlay.visible.store(true, Ordering::Relaxed);
+                                Ok(())
+                            }
+                            None => Err("the overlay is not enabled (gui.enable = false or --no-gui)".to_string()),
+                        };
+                        let _ = message.reply.send(result);
+                    }
+                    // status 和 subscribe 由控制套接字直接处理
+                    control::Request::Status | control::Request::Subscribe => {
+                        let _ = message.reply.send(Ok(()));
+                    }
+                }
+            }
+            Some(()) = recv(&mut toggle_signal) => {
+                info!("Received SIGUSR1, toggling pause");
+                previous_process_name.clear();
+                match pause.toggle(config.privacy.pause_minutes) {
+                    Ok(event) => {
+                        let _ = event_sender.send(event);
+                    }
+                    Err(e) => warn!("Failed to pause reporting: {}", e),
+                }
+            }
+            Some(()) = recv(&mut interrupt_signal) => {
+                info!("Received SIGINT, shutting down");
+                break;
+            }
+            Some(()) = recv(&mut terminate_signal) => {
+                info!("Received SIGTERM, shutting down");
+                break;
+            }
+            Some(()) = async {
+                match overlay_closed.as_mut() {
+                    Some(closed) => closed.changed().await.ok(),
+                    None => std::future::pending().await,
+                }
+            } => {
+                info!("Overlay closed, shutting down");
+                break;
+            }
+        }
+    }
+
+    notifier.stopping();
+    let _ = event_sender.send(control::Event::Stopping);
+    for reply in report_replies.drain(..) {
+        let _ = reply.send(Err("daemon is shutting down".to_string()));
+    }
+    if let Some(history) = history.as_mut() {
+        history.finish(Utc::now());
+    }
+    if let Some(server) = http_server.take() {
+        server.abort();
+    }
+
+    // 重发离线队列并发送离线状态，最多等 shutdown.timeout 秒；再按一次 Ctrl+C 立即退出
+    if config.shutdown.report && !targets.is_empty() {
+        let activity = Activity {
+            process: config.shutdown.process.clone(),
+            extend: config.shutdown.extend.clone(),
+            media: None,
+            timestamp: Utc::now().timestamp(),
+            window: None,
+        };
+        let timeout = Duration::from_secs(config.shutdown.timeout);
+        let offline = join_all(targets.iter_mut().map(|target| target.report_offline(&activity)));
+        tokio::select! {
+            result = tokio::time::timeout(timeout, offline) => {
+                if result.is_err() {
+                    warn!("Offline report did not finish within {}s", config.shutdown.timeout);
+                }
+            }
+            Some(()) = recv(&mut interrupt_signal) => warn!("Interrupted again, skipping the offline report"),
+        }
+    }
+    if let Some(path) = control_path {
+        let _ = std::fs::remove_file(path);
     }
+    info!("Stopped");
 }
 
-async fn report(
-    process_name: &str,
-    media_title: &str,
-    media_artist: &str,
-    media_thumbnail: &str,
-    extend: &str,
+/// 监听一个信号，失败时返回 None，对应的分支不会触发
+fn listen(kind: SignalKind, name: &str) -> Option<Signal> {
+    match signal(kind) {
+        Ok(signal) => Some(signal),
+        Err(e) => {
+            warn!("Failed to listen for {}: {}", name, e);
+            None
+        }
+    }
+}
+
+async fn recv(signal: &mut Option<Signal>) -> Option<()> {
+    match signal.as_mut() {
+        Some(signal) => signal.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+fn open_history(config: &Config) -> Option<History> {
+    if !config.history.enable {
+        return None;
+    }
+    match History::open(&config.history, config.watch_time) {
+        Ok(history) => Some(history),
+        Err(e) => {
+            error!("Failed to open history {}: {}", config.history.dir().display(), e);
+            None
+        }
+    }
+}
+
+fn open_activitywatch(config: &Config) -> Option<ActivityWatch> {
+    if !config.activitywatch.enable {
+        return None;
+    }
+    info!("Sending heartbeats to ActivityWatch at {}", config.activitywatch.url);
+    Some(ActivityWatch::new(&config.activitywatch, config.watch_time))
+}
+
+fn start_http(
     config: &Config,
-) -> Result<(), Box<dyn Error>> {
-    reportprocess::process_report(
-        process_name,
-        media_title,
-        media_artist,
-        media_thumbnail,
-        extend,
-        &config.api_key,
-        &config.api_url,
-        config.watch_time,
-        config.log_enable,
-    )
-    .await?;
-    Ok(())
+    status: &Arc<Mutex<status_window::AppStatus>>,
+    metrics: &Arc<Mutex<metrics::Metrics>>,
+    events: &tokio::sync::broadcast::Sender<control::Event>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    if !config.http.enable {
+        return None;
+    }
+    let shared = http_api::Shared {
+        status: status.clone(),
+        metrics: metrics.clone(),
+        events: events.clone(),
+    };
+    match http_api::serve(&config.http, config.history.dir(), &config.rules, shared) {
+        Ok((address, handle)) => {
+            info!("HTTP API listening on http://{}", address);
+            Some(handle)
+        }
+        Err(e) => {
+            error!("Failed to start HTTP API on {}: {}", config.http.bind, e);
+            None
+        }
+    }
 }
 
-#[tokio::main]
-async fn main() {
-    let mut config = Config::default();
+fn target_auth(target_config: &TargetConfig) -> Auth {
+    Auth {
+        scheme: target_config.auth_scheme.clone(),
+        key: target_config.key.clone(),
+    }
+}
 
-    match get_env_file::get_env_file() {
-        Ok((api_url, api_key, watch_time, media_enable, log_enable, gui_enable)) => {
-            config.api_url = api_url;
-            config.api_key = api_key;
-            config.watch_time = watch_time;
-            config.media_enable = media_enable;
-            config.log_enable = log_enable;
-            config.gui_enable = gui_enable;
+fn build_targets(config: &Config) -> Vec<Target> {
+    let mut targets = Vec::new();
+    let timeout = reporter::report_timeout(config.watch_time);
+
+    for target_config in &config.targets {
+        let reporter: Box<dyn Reporter> = match target_config.kind.as_str() {
+            "mixspace" => Box::new(MixSpaceReporter {
+                api_url: target_config.url.clone().unwrap_or_default(),
+                auth: target_auth(target_config),
+                timeout,
+            }),
+            "webhook" => Box::new(WebhookReporter {
+                url: target_config.url.clone().unwrap_or_default(),
+                method: target_config.method.clone(),
+                headers: target_config
+                    .headers
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect(),
+                auth: target_auth(target_config),
+                timeout,
+            }),
+            "jsonl" => Box::new(JsonLinesReporter {
+                path: target_config.jsonl_path(),
+            }),
+            "stdout" => Box::new(StdoutReporter),
+            "unix" => Box::new(UnixSocketReporter {
+                path: target_config.path.clone().unwrap_or_default(),
+            }),
+            _ => {
+                error!("Unknown reporter: {}", target_config.kind);
+                continue;
+            }
+        };
+
+        // 离线队列：上报失败时暂存到磁盘，恢复连接后按顺序重发
+        let queue = if config.queue.enable && reporter.wants_queue() {
+            let queue_path = config
+                .queue
+                .dir()
+                .join(format!("queue-{}.jsonl", target_config.name));
+            match OfflineQueue::open(&queue_path, config.queue.max_entries, config.queue.max_age) {
+                Ok(queue) => {
+                    if !queue.is_empty() {
+                        info!("Loaded {} pending reports from {}", queue.len(), queue_path.display());
+                    }
+                    Some(queue)
+                }
+                Err(e) => {
+                    error!("Failed to open offline queue {}: {}", queue_path.display(), e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        targets.push(Target::new(
+            target_config,
+            &config.rules.exclude,
+            reporter,
+            queue,
+            config.watch_time,
+        ));
+    }
+
+    targets
+}
+
+/// 状态、事件和指标中公开的活动：暂停时已经是占位状态，再套用全局的排除列表和应用规则；
+/// 被规则丢弃时为 None
+fn public_activity(config: &Config, rules: &AppRules, activity: &Activity) -> Option<Activity> {
+    let mut activity = rules.apply(activity).ok()?;
+    if config.rules.exclude.iter().any(|name| name.eq_ignore_ascii_case(&activity.process)) {
+        activity.process.clear();
+        activity.extend.clear();
+        activity.window = None;
+    }
+    Some(activity)
+}
+
+/// 公开的前台窗口；占位状态和命中 label、busy 规则时只有进程名
+fn public_window(activity: &Activity) -> get_active_window::WindowInfo {
+    get_active_window::WindowInfo {
+        process: activity.process.clone(),
+        ..activity.window.clone().unwrap_or_default()
+    }
+}
+
+/// once 子命令：检测一次当前窗口和媒体并打印，不上报；--dry-run 时再打印各目标将收到的内容
+fn once(matches: &ArgMatches) {
+    let dry_run = matches.get_flag("dry-run");
+    // 没有配置文件时也能用，媒体播放器列表使用默认值；dry-run 需要完整校验过的配置
+    let config = if dry_run {
+        get_env_file::get_env_file(matches).unwrap_or_else(|e| {
+            eprintln!("Failed to load config: {}", e);
+            exit(1);
+        })
+    } else {
+        get_env_file::load_unchecked(matches).unwrap_or_default()
+    };
+
+    let mut window = get_active_window::get_active_window_info().unwrap_or_else(|e| {
+        eprintln!("Failed to get active window: {}", e);
+        get_active_window::WindowInfo::default()
+    });
+    // 用假的窗口信息测试规则
+    let pretend = |name: &str| matches.get_one::<String>(name).cloned();
+    if let Some(class) = pretend("class") {
+        window = get_active_window::WindowInfo::new(&class, &window.title, None);
+    }
+    if let Some(exe) = pretend("exe") {
+        window.exe = exe;
+    }
+    if let Some(title) = pretend("title") {
+        window.title = title;
+    }
+
+    let media = if config.media.enable {
+        get_media::get_media_metadata(&config.media.players)
+    } else {
+        None
+    };
+    let extend_texts = ExtendTexts::compile(&config.extend).unwrap_or_else(|e| {
+        eprintln!("Invalid extend texts: {}", e);
+        ExtendTexts::default()
+    });
+    let activity = Activity {
+        extend: extend_texts.render(&ExtendContext {
+            window: &window,
+            media: media.as_ref(),
+            duration: chrono::Duration::zero(),
+        }),
+        process: window.process.clone(),
+        media,
+        timestamp: Utc::now().timestamp(),
+        window: Some(window.clone()),
+    };
+    let previews: Vec<(String, Result<serde_json::Value, String>)> = if dry_run {
+        config
+            .targets
+            .iter()
+            .map(|target_config| {
+                // 只用来生成 payload，不会真的发送
+                let target = Target::new(
+                    target_config,
+                    &config.rules.exclude,
+                    Box::new(StdoutReporter),
+                    None,
+                    config.watch_time,
+                );
+                (target_config.name.clone(), target.preview(&activity))
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    if matches.get_one::<String>("format").map(String::as_str) == Some("json") {
+        let mut output = activity.to_json();
+        if dry_run {
+            output["window"] = serde_json::json!({
+                "class": window.class,
+                "exe": window.exe,
+                "title": window.title,
+            });
+            output["targets"] = previews
+                .iter()
+                .map(|(name, preview)| match preview {
+                    Ok(payload) => serde_json::json!({ "name": name, "payload": payload }),
+                    Err(reason) => serde_json::json!({ "name": name, "skipped": reason }),
+                })
+                .collect();
+        }
+        println!("{}", output);
+        return;
+    }
+    println!("Process: {}", activity.process);
+    println!("Extend:  {}", activity.extend);
+    match &activity.media {
+        Some(media) => println!(
+            "Media:   {} - {}",
+            media.title.clone().unwrap_or_default(),
+            media.artist.clone().unwrap_or_default()
+        ),
+        None => println!("Media:   (none)"),
+    }
+    if !dry_run {
+        return;
+    }
+    println!("Class:   {}", window.class);
+    println!("Exe:     {}", window.exe);
+    println!("Title:   {}", window.title);
+    println!("Would send:");
+    for (name, preview) in previews {
+        match preview {
+            Ok(payload) => println!("  {:<16} {}", name, payload),
+            Err(reason) => println!("  {:<16} (nothing, {})", name, reason),
+        }
+    }
+}
+
+/// report、export 只读取本地历史，没有配置文件时使用默认目录
+fn history_config(matches: &ArgMatches) -> Config {
+    match get_env_file::config_path(matches) {
+        Ok(Some(_)) => get_env_file::load_unchecked(matches).unwrap_or_else(|e| {
+            eprintln!("Failed to load config: {}", e);
+            exit(1);
+        }),
+        _ => get_env_file::load_unchecked(matches).unwrap_or_default(),
+    }
+}
+
+/// export 子命令：导出时间范围内开始的时间段
+fn export(matches: &ArgMatches, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let (from, to) = time_report::date_range(matches)?;
+    let start = time_report::local_midnight(from);
+    let end = time_report::local_midnight(to + chrono::Duration::days(1));
+    let spans: Vec<history::Span> = history::read(&config.history.dir(), from, to)?
+        .into_iter()
+        .filter(|span| start <= span.start && span.start < end)
+        .collect();
+
+    let output = match matches.get_one::<String>("format").map(String::as_str) {
+        Some("jsonl") => spans
+            .iter()
+            .map(|span| serde_json::to_string(span).map(|line| line + "\n"))
+            .collect::<Result<String, _>>()?,
+        _ => serde_json::to_string_pretty(&activitywatch::export(&spans, &config.activitywatch))? + "\n",
+    };
+    match matches.get_one::<String>("output") {
+        Some(path) => {
+            std::fs::write(path, output)?;
+            eprintln!("Exported {} spans to {}", spans.len(), path);
+        }
+        None => print!("{}", output),
+    }
+    Ok(())
+}
+
+/// run 子命令（默认）：启动上报守护进程
+async fn run_daemon(matches: ArgMatches) {
+    // 同一用户只运行一个实例，不带参数再次启动时让正在运行的实例显示悬浮窗
+    let _lock = match instance::acquire() {
+        Ok(Ok(lock)) => Some(lock),
+        Ok(Err(pid)) => {
+            let pid = pid.map(|pid| format!(" (pid {})", pid)).unwrap_or_default();
+            // 正在运行的实例不会使用这次的配置，不能悄悄忽略
+            let mut ignored = get_env_file::override_args(&matches);
+            if let Some(config) = matches.get_one::<String>("config") {
+                ignored.splice(0..0, ["--config".to_string(), config.clone()]);
+            }
+            if !ignored.is_empty() {
+                eprintln!(
+                    "processforlinux is already running{}, it does not use the options of this launch: {}",
+                    pid,
+                    ignored.join(" ")
+                );
+                eprintln!("Edit the config and run `processforlinux reload`, or stop the running instance first");
+                exit(1);
+            }
+            match instance::forward(&control::Request::ShowOverlay) {
+                Ok(()) => println!("processforlinux is already running{}, showing its overlay", pid),
+                Err(e) => {
+                    eprintln!("processforlinux is already running{}: {}", pid, e);
+                    exit(1);
+                }
+            }
+            return;
         }
         Err(e) => {
-            eprintln!("Failed to get env file: {}", e);
+            eprintln!("{}, skipping the single-instance check", e);
+            None
+        }
+    };
+    let config = match get_env_file::get_env_file(&matches) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Failed to load config: {}", e);
             exit(1);
         }
     };
+    logging::init(Some(&config));
+
+    // 控制请求通道，控制套接字和悬浮窗共用
+    let (control_sender, control_receiver) = tokio::sync::mpsc::unbounded_channel();
 
-    if config.gui_enable {
+    if config.gui.enable {
         // GUI模式：创建channel
         let (status_sender, status_receiver) = mpsc::channel();
+        let gui_control = control_sender.clone();
+        let visible = Arc::new(AtomicBool::new(true));
+        let close = Arc::new(AtomicBool::new(false));
+        let (closed_sender, closed) = tokio::sync::watch::channel(false);
+        let overlay = status_window::OverlayHandle {
+            status: status_sender,
+            visible: visible.clone(),
+            closed,
+        };
+        let monitor_close = close.clone();
 
-        // 在子线程中运行监控循环
-        thread::spawn(move || {
+        // 在子线程中运行监控循环，退出后通知悬浮窗关闭
+        let monitor = thread::spawn(move || {
             // 创建运行时用于子线程
             let rt = tokio::runtime::Runtime::new().unwrap();
             rt.block_on(async {
-                println!("Starting monitor with GUI...");
-                run_loop(config, Some(status_sender)).await;
+                info!("Starting monitor with GUI");
+                run_loop(config, matches, Some(overlay), (control_sender, control_receiver)).await;
             });
+            monitor_close.store(true, Ordering::Relaxed);
         });
 
-        // 在主线程中启动GUI
-        println!("Launching overlay window...");
-        status_window::run_status_window(status_receiver);
+        // 在主线程中启动GUI；悬浮窗被关闭时让监控循环退出，等它发完离线状态
+        info!("Launching overlay window");
+        status_window::run_status_window(status_receiver, gui_control, visible, close);
+        let _ = closed_sender.send(true);
+        if monitor.join().is_err() {
+            error!("Monitor thread panicked");
+            exit(1);
+        }
     } else {
         // 控制台模式：不使用GUI
-        println!("Starting monitor in console mode...");
-        run_loop(config, None).await;
+        info!("Starting monitor in console mode");
+        run_loop(config, matches, None, (control_sender, control_receiver)).await;
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let matches = get_env_file::cli().get_matches();
+    // 守护进程读取配置后按 [log] 初始化，其他子命令只输出警告和错误
+    if !matches!(matches.subcommand_name(), None | Some("run")) {
+        logging::init(None);
+    }
+
+    match matches.subcommand() {
+        Some(("install-service", sub_matches)) => {
+            if let Err(e) = systemd::install(sub_matches) {
+                eprintln!("Failed to install service: {}", e);
+                exit(1);
+            }
+        }
+        Some(("uninstall-service", _)) => {
+            if let Err(e) = systemd::uninstall() {
+                eprintln!("Failed to uninstall service: {}", e);
+                exit(1);
+            }
+        }
+        Some(("install-autostart", sub_matches)) => {
+            if let Err(e) = autostart::install(sub_matches) {
+                eprintln!("Failed to install autostart entry: {}", e);
+                exit(1);
+            }
+        }
+        Some(("uninstall-autostart", _)) => {
+            if let Err(e) = autostart::uninstall() {
+                eprintln!("Failed to uninstall autostart entry: {}", e);
+                exit(1);
+            }
+        }
+        Some(("set-key", sub_matches)) => {
+            if let Err(e) = get_env_file::set_key(sub_matches) {
+                eprintln!("Failed to store key: {}", e);
+                exit(1);
+            }
+        }
+        Some(("config", sub_matches)) => {
+            if let Some(("show", show_matches)) = sub_matches.subcommand() {
+                if let Err(e) = get_env_file::show_config(show_matches) {
+                    eprintln!("Invalid config: {}", e);
+                    exit(1);
+                }
+            }
+        }
+        Some(("once", sub_matches)) => once(sub_matches),
+        Some(("report", sub_matches)) => {
+            if let Err(e) = time_report::run(sub_matches, &history_config(sub_matches)) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(("export", sub_matches)) => {
+            if let Err(e) = export(sub_matches, &history_config(sub_matches)) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(("status", sub_matches)) => {
+            let format = sub_matches.get_one::<String>("format").unwrap();
+            if let Err(e) = control::print_status(format) {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some((command @ ("pause" | "resume" | "report-now" | "reload" | "show-overlay"), sub_matches)) => {
+            let (request, done) = match command {
+                "pause" => (
+                    control::Request::Pause {
+                        minutes: sub_matches.get_one::<u64>("minutes").copied(),
+                    },
+                    "Reporting paused",
+                ),
+                "resume" => (control::Request::Resume, "Reporting resumed"),
+                "report-now" => (control::Request::Report, "Reported"),
+                "show-overlay" => (control::Request::ShowOverlay, "Overlay shown"),
+                _ => (control::Request::Reload, "Config reloaded"),
+            };
+            match control::send(&request) {
+                Ok(_) => println!("{}", done),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit(1);
+                }
+            }
+        }
+        Some(("events", _)) => {
+            if let Err(e) = control::print_events() {
+                eprintln!("{}", e);
+                exit(1);
+            }
+        }
+        Some(("doctor", sub_matches)) => {
+            if !doctor::run(sub_matches).await {
+                exit(1);
+            }
+        }
+        Some(("run", sub_matches)) => run_daemon(sub_matches.clone()).await,
+        _ => run_daemon(matches).await,
     }
 }
@@ -0,0 +1,280 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/metrics.rs
+ */
+
+use crate::status_window::{AppStatus, TargetHealth};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// 上报耗时的桶（秒）
+pub const REPORT_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+/// 窗口检测耗时的桶（秒），xprop、kdotool 等外部命令通常在几十毫秒内
+pub const DETECTION_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+
+/// Prometheus 直方图，counts[i] 为落在第 i 个桶（不累加）的次数
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, seconds: f64) {
+        if let Some(index) = self.bounds.iter().position(|bound| seconds <= *bound) {
+            self.counts[index] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// 单个上报目标的指标，重新加载配置时同名目标沿用
+#[derive(Debug, Clone)]
+pub struct TargetMetrics {
+    pub latency: Histogram,
+    /// HTTP 状态码 → 次数，只有 HTTP 上报方式才有
+    pub responses: BTreeMap<u16, u64>,
+    pub last_success: Option<DateTime<Utc>>,
+}
+
+impl Default for TargetMetrics {
+    fn default() -> Self {
+        TargetMetrics {
+            latency: Histogram::new(REPORT_BUCKETS),
+            responses: BTreeMap::new(),
+            last_success: None,
+        }
+    }
+}
+
+/// AppStatus 之外的指标，由 run_loop 维护，HTTP 接口的 /metrics 读取
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    pub detection: Histogram,
+    pub last_check: Option<DateTime<Utc>>,
+    /// 当前应用名和窗口类名，暂停时为占位内容
+    pub app: String,
+    pub class: String,
+    pub targets: BTreeMap<String, TargetMetrics>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics {
+            detection: Histogram::new(DETECTION_BUCKETS),
+            last_check: None,
+            app: String::new(),
+            class: String::new(),
+            targets: BTreeMap::new(),
+        }
+    }
+}
+
+/// 按 Prometheus 文本格式输出
+pub fn render(status: &AppStatus, metrics: &Metrics) -> String {
+    let mut out = String::new();
+    let stats = &status.stats;
+
+    header(&mut out, "processforlinux_start_time_seconds", "gauge", "Start time of the daemon");
+    sample(&mut out, "processforlinux_start_time_seconds", "", stats.start_time.timestamp());
+    header(&mut out, "processforlinux_checks_total", "counter", "Active window checks");
+    sample(&mut out, "processforlinux_checks_total", "", stats.total_checks);
+    header(
+        &mut out,
+        "processforlinux_window_detections_total",
+        "counter",
+        "Active window detections by result",
+    );
+    sample(&mut out, "processforlinux_window_detections_total", "result=\"success\"", stats.success_count);
+    sample(&mut out, "processforlinux_window_detections_total", "result=\"failure\"", stats.failure_count);
+    header(
+        &mut out,
+        "processforlinux_window_detection_duration_seconds",
+        "histogram",
+        "Time spent detecting the active window",
+    );
+    histogram(&mut out, "processforlinux_window_detection_duration_seconds", "", &metrics.detection);
+    if let Some(last_check) = metrics.last_check {
+        header(
+            &mut out,
+            "processforlinux_last_check_timestamp_seconds",
+            "gauge",
+            "Time of the last active window check",
+        );
+        sample(&mut out, "processforlinux_last_check_timestamp_seconds", "", last_check.timestamp());
+    }
+    header(&mut out, "processforlinux_paused", "gauge", "Whether reporting is paused");
+    sample(&mut out, "processforlinux_paused", "", status.paused as u8);
+    header(&mut out, "processforlinux_current_app_info", "gauge", "The current app, always 1");
+    let labels = format!("app=\"{}\",class=\"{}\"", escape(&metrics.app), escape(&metrics.class));
+    sample(&mut out, "processforlinux_current_app_info", &labels, 1);
+
+    let targets: Vec<(&TargetHealth, TargetMetrics)> = status
+        .targets
+        .iter()
+        .map(|health| (health, metrics.targets.get(&health.name).cloned().unwrap_or_default()))
+        .collect();
+    if targets.is_empty() {
+        return out;
+    }
+    let target_label = |health: &TargetHealth| format!("target=\"{}\"", escape(&health.name));
+
+    header(&mut out, "processforlinux_reports_total", "counter", "Reports by target and result");
+    for (health, _) in &targets {
+        let label = target_label(health);
+        sample(&mut out, "processforlinux_reports_total", &format!("{},result=\"success\"", label), health.success_count);
+        sample(&mut out, "processforlinux_reports_total", &format!("{},result=\"failure\"", label), health.failure_count);
+    }
+    header(
+        &mut out,
+        "processforlinux_report_duration_seconds",
+        "histogram",
+        "Time spent sending a report, including queued retries",
+    );
+    for (health, target) in &targets {
+        histogram(&mut out, "processforlinux_report_duration_seconds", &target_label(health), &target.latency);
+    }
+    header(
+        &mut out,
+        "processforlinux_report_responses_total",
+        "counter",
+        "HTTP responses by target and status code",
+    );
+    for (health, target) in &targets {
+        for (code, count) in &target.responses {
+            let labels = format!("{},code=\"{}\"", target_label(health), code);
+            sample(&mut out, "processforlinux_report_responses_total", &labels, count);
+        }
+    }
+    header(&mut out, "processforlinux_queue_depth", "gauge", "Reports waiting in the offline queue");
+    for (health, _) in &targets {
+        sample(&mut out, "processforlinux_queue_depth", &target_label(health), health.queued);
+    }
+    header(
+        &mut out,
+        "processforlinux_last_success_timestamp_seconds",
+        "gauge",
+        "Time of the last successful report",
+    );
+    for (health, target) in &targets {
+        if let Some(last_success) = target.last_success {
+            sample(
+                &mut out,
+                "processforlinux_last_success_timestamp_seconds",
+                &target_label(health),
+                last_success.timestamp(),
+            );
+        }
+    }
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample(out: &mut String, name: &str, labels: &str, value: impl std::fmt::Display) {
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+/// 直方图的 _bucket（累加）、_sum 和 _count
+fn histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram) {
+    let separator = if labels.is_empty() { "" } else { "," };
+    let mut cumulative = 0;
+    for (bound, count) in histogram.bounds.iter().zip(&histogram.counts) {
+        cumulative += count;
+        let labels = format!("{}{}le=\"{}\"", labels, separator, bound);
+        sample(out, &format!("{}_bucket", name), &labels, cumulative);
+    }
+    let labels_inf = format!("{}{}le=\"+Inf\"", labels, separator);
+    sample(out, &format!("{}_bucket", name), &labels_inf, histogram.count);
+    sample(out, &format!("{}_sum", name), labels, histogram.sum);
+    sample(out, &format!("{}_count", name), labels, histogram.count);
+}
+
+/// 标签值中的 \、" 和换行需要转义
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("a\\b\"c\"\nd"), "a\\\\b\\\"c\\\"\\nd");
+    }
+
+    #[test]
+    fn accumulates_histogram_buckets() {
+        let mut detection = Histogram::new(&[0.1, 1.0]);
+        detection.observe(0.05);
+        detection.observe(0.5);
+        detection.observe(5.0);
+        let mut out = String::new();
+        histogram(&mut out, "m", "", &detection);
+        assert_eq!(
+            out,
+            "m_bucket{le=\"0.1\"} 1\nm_bucket{le=\"1\"} 2\nm_bucket{le=\"+Inf\"} 3\nm_sum 5.55\nm_count 3\n"
+        );
+    }
+
+    #[test]
+    fn renders_escaped_app_and_target_labels() {
+        let status = AppStatus {
+            targets: vec![TargetHealth {
+                name: "my \"blog\"".to_string(),
+                success_count: 2,
+                failure_count: 1,
+                queued: 3,
+                last_error: None,
+            }],
+            ..Default::default()
+        };
+        let mut metrics = Metrics {
+            app: "Code".to_string(),
+            class: "C:\\app\nx".to_string(),
+            ..Default::default()
+        };
+        let mut target = TargetMetrics::default();
+        target.responses.insert(503, 1);
+        metrics.targets.insert("my \"blog\"".to_string(), target);
+
+        let out = render(&status, &metrics);
+        assert!(out.contains("processforlinux_current_app_info{app=\"Code\",class=\"C:\\\\app\\nx\"} 1\n"));
+        assert!(out.contains("processforlinux_reports_total{target=\"my \\\"blog\\\"\",result=\"failure\"} 1\n"));
+        assert!(out.contains("processforlinux_report_responses_total{target=\"my \\\"blog\\\"\",code=\"503\"} 1\n"));
+        assert!(out.contains("processforlinux_queue_depth{target=\"my \\\"blog\\\"\"} 3\n"));
+        assert!(!out.contains("processforlinux_last_success_timestamp_seconds{"));
+        // 每个样本都是一行，标签值里的换行已经转义
+        assert!(out.lines().all(|line| line.starts_with('#') || line.starts_with("processforlinux_")));
+    }
+
+    #[test]
+    fn omits_target_metrics_without_targets() {
+        let out = render(&AppStatus::default(), &Metrics::default());
+        assert!(out.contains("# TYPE processforlinux_checks_total counter\n"));
+        assert!(!out.contains("processforlinux_reports_total"));
+    }
+}
@@ -0,0 +1,237 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/offline_queue.rs
+ */
+
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// 队列中的一条待重发上报，payload 保留原始时间戳
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedReport {
+    pub queued_at: i64,
+    pub payload: Value,
+}
+
+impl QueuedReport {
+    fn to_line(&self) -> String {
+        json!({
+            "queued_at": self.queued_at,
+            "payload": self.payload,
+        })
+        .to_string()
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let value: Value = serde_json::from_str(line).ok()?;
+        Some(QueuedReport {
+            queued_at: value.get("queued_at")?.as_i64()?,
+            payload: value.get("payload")?.clone(),
+        })
+    }
+}
+
+/// 持久化在磁盘上的离线上报队列（JSON Lines 格式）
+pub struct OfflineQueue {
+    path: PathBuf,
+    max_entries: usize,
+    max_age_secs: i64,
+    entries: VecDeque<QueuedReport>,
+}
+
+impl OfflineQueue {
+    /// 打开（或新建）队列文件，损坏的行会被跳过
+    pub fn open(path: &Path, max_entries: usize, max_age_secs: i64) -> Result<Self, Box<dyn Error>> {
+        let mut entries = VecDeque::new();
+
+        if path.exists() {
+            let reader = BufReader::new(File::open(path)?);
+            for line in reader.lines() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match QueuedReport::from_line(&line) {
+                    Some(entry) => entries.push_back(entry),
+                    None => warn!("Skipping malformed queue entry: {}", line),
+                }
+            }
+        }
+
+        let mut queue = OfflineQueue {
+            path: path.to_path_buf(),
+            max_entries,
+            max_age_secs,
+            entries,
+        };
+        if queue.prune() {
+            queue.persist()?;
+        }
+        Ok(queue)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn front(&self) -> Option<&QueuedReport> {
+        self.entries.front()
+    }
+
+    /// 移除队首（已成功重发的）条目
+    pub fn pop_front(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.entries.pop_front().is_some() {
+            self.persist()?;
+        }
+        Ok(())
+    }
+
+    /// 加入一条上报失败的 payload
+    ///
+    /// 与队尾内容相同（仅时间戳不同）的心跳上报会被合并，只保留最早的一条。
+    pub fn push(&mut self, payload: Value) -> Result<(), Box<dyn Error>> {
+        let redundant = self
+            .entries
+            .back()
+            .is_some_and(|last| same_content(&last.payload, &payload));
+
+        if !redundant {
+            self.entries.push_back(QueuedReport {
+                queued_at: Utc::now().timestamp(),
+                payload,
+            });
+        }
+
+        self.prune();
+        self.persist()
+    }
+
+    /// 按数量和时间上限清理过期条目，返回是否有条目被移除
+    fn prune(&mut self) -> bool {
+        let before = self.entries.len();
+        let oldest_allowed = Utc::now().timestamp() - self.max_age_secs;
+
+        self.entries.retain(|entry| entry.queued_at >= oldest_allowed);
+        while self.entries.len() > self.max_entries {
+            self.entries.pop_front();
+        }
+
+        self.entries.len() != before
+    }
+
+    /// 先写临时文件再重命名，避免写到一半时崩溃导致队列损坏
+    fn persist(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = self.path.with_extension("tmp");
+        let mut file = File::create(&tmp_path)?;
+        for entry in &self.entries {
+            writeln!(file, "{}", entry.to_line())?;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+/// 比较两个 payload 是否只有时间戳不同
+fn same_content(a: &Value, b: &Value) -> bool {
+    let strip = |value: &Value| {
+        let mut value = value.clone();
+        if let Some(object) = value.as_object_mut() {
+            object.remove("timestamp");
+        }
+        value
+    };
+    strip(a) == strip(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn payload(process: &str, timestamp: i64) -> Value {
+        json!({ "process": process, "timestamp": timestamp })
+    }
+
+    fn processes(queue: &OfflineQueue) -> Vec<String> {
+        queue
+            .entries
+            .iter()
+            .map(|entry| entry.payload["process"].as_str().unwrap_or_default().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn replays_in_order_after_reopen() {
+        let dir = TempDir::new("queue-order");
+        let path = dir.join("queue.jsonl");
+        let mut queue = OfflineQueue::open(&path, 10, 3600).unwrap();
+        for (index, process) in ["Code", "Firefox", "Telegram"].iter().enumerate() {
+            queue.push(payload(process, index as i64)).unwrap();
+        }
+
+        let mut queue = OfflineQueue::open(&path, 10, 3600).unwrap();
+        assert_eq!(processes(&queue), ["Code", "Firefox", "Telegram"]);
+        queue.pop_front().unwrap();
+        let queue = OfflineQueue::open(&path, 10, 3600).unwrap();
+        assert_eq!(queue.front().unwrap().payload, payload("Firefox", 1));
+    }
+
+    #[test]
+    fn trims_oldest_entries() {
+        let dir = TempDir::new("queue-trim");
+        let path = dir.join("queue.jsonl");
+        let mut queue = OfflineQueue::open(&path, 2, 3600).unwrap();
+        for (index, process) in ["Code", "Firefox", "Telegram"].iter().enumerate() {
+            queue.push(payload(process, index as i64)).unwrap();
+        }
+        assert_eq!(processes(&queue), ["Firefox", "Telegram"]);
+    }
+
+    #[test]
+    fn merges_repeated_heartbeats() {
+        let dir = TempDir::new("queue-merge");
+        let path = dir.join("queue.jsonl");
+        let mut queue = OfflineQueue::open(&path, 10, 3600).unwrap();
+        queue.push(payload("Code", 1)).unwrap();
+        queue.push(payload("Code", 2)).unwrap();
+        queue.push(payload("Firefox", 3)).unwrap();
+        queue.push(payload("Code", 4)).unwrap();
+        assert_eq!(processes(&queue), ["Code", "Firefox", "Code"]);
+        // 保留最早的一条
+        assert_eq!(queue.front().unwrap().payload["timestamp"], 1);
+    }
+
+    #[test]
+    fn drops_expired_and_malformed_entries_on_open() {
+        let dir = TempDir::new("queue-expired");
+        let path = dir.join("queue.jsonl");
+        let now = Utc::now().timestamp();
+        let lines = [
+            QueuedReport { queued_at: now - 7200, payload: payload("Old", 1) }.to_line(),
+            "not json".to_string(),
+            QueuedReport { queued_at: now, payload: payload("New", 2) }.to_line(),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        let queue = OfflineQueue::open(&path, 10, 3600).unwrap();
+        assert_eq!(processes(&queue), ["New"]);
+        // 清理后的结果已经写回文件
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+}
@@ -0,0 +1,106 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/pause.rs
+ */
+
+use crate::control::Event;
+use chrono::{DateTime, TimeDelta, Utc};
+use tracing::info;
+
+/// 一次暂停的最长时间（分钟），即一周
+pub const MAX_PAUSE_MINUTES: u64 = 10080;
+
+/// 暂停 minutes 分钟后自动恢复的时间，超出范围时返回错误
+pub fn resume_time(now: DateTime<Utc>, minutes: Option<u64>) -> Result<Option<DateTime<Utc>>, String> {
+    let Some(minutes) = minutes else {
+        return Ok(None);
+    };
+    if !(1..=MAX_PAUSE_MINUTES).contains(&minutes) {
+        return Err(format!("pause minutes must be between 1 and {}, got {}", MAX_PAUSE_MINUTES, minutes));
+    }
+    TimeDelta::try_minutes(minutes as i64)
+        .and_then(|duration| now.checked_add_signed(duration))
+        .map(Some)
+        .ok_or_else(|| format!("pause of {} minutes is out of range", minutes))
+}
+
+/// 暂停上报的状态，可以由控制套接字、悬浮窗和 SIGUSR1 触发
+#[derive(Debug, Clone, Default)]
+pub struct PauseState {
+    paused: bool,
+    /// 自动恢复的时间，为空表示一直暂停到手动恢复
+    until: Option<DateTime<Utc>>,
+}
+
+impl PauseState {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn until(&self) -> Option<DateTime<Utc>> {
+        self.until
+    }
+
+    /// 暂停上报，返回要广播的事件；时长超出范围时不改变状态并返回错误
+    pub fn pause(&mut self, minutes: Option<u64>) -> Result<Event, String> {
+        self.until = resume_time(Utc::now(), minutes)?;
+        self.paused = true;
+        match self.until {
+            Some(until) => info!("Reporting paused until {}", until.format("%Y-%m-%d %H:%M:%S")),
+            None => info!("Reporting paused"),
+        }
+        Ok(Event::Paused { until: self.until })
+    }
+
+    pub fn resume(&mut self) -> Event {
+        self.paused = false;
+        self.until = None;
+        info!("Reporting resumed");
+        Event::Resumed
+    }
+
+    /// 暂停中切换为恢复，否则按默认时长暂停
+    pub fn toggle(&mut self, default_minutes: Option<u64>) -> Result<Event, String> {
+        if self.paused {
+            Ok(self.resume())
+        } else {
+            self.pause(default_minutes)
+        }
+    }
+
+    /// 到了自动恢复的时间就恢复，返回要广播的事件
+    pub fn expire(&mut self, now: DateTime<Utc>) -> Option<Event> {
+        if self.paused && self.until.is_some_and(|until| now >= until) {
+            info!("Pause expired");
+            return Some(self.resume());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_out_of_range_minutes() {
+        let now = Utc::now();
+        assert_eq!(resume_time(now, None), Ok(None));
+        assert_eq!(resume_time(now, Some(15)), Ok(Some(now + TimeDelta::minutes(15))));
+        assert!(resume_time(now, Some(MAX_PAUSE_MINUTES)).is_ok());
+        for minutes in [0, MAX_PAUSE_MINUTES + 1, 100_000_000_000_000, u64::MAX] {
+            assert!(resume_time(now, Some(minutes)).is_err(), "{}", minutes);
+        }
+    }
+
+    #[test]
+    fn failed_pause_keeps_state() {
+        let mut pause = PauseState::default();
+        assert!(pause.pause(Some(u64::MAX)).is_err());
+        assert!(!pause.is_paused());
+        assert!(pause.pause(Some(10)).is_ok());
+        assert!(pause.expire(Utc::now() + TimeDelta::minutes(11)).is_some());
+        assert!(!pause.is_paused());
+    }
+}
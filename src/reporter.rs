@@ -0,0 +1,178 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/reporter.rs
+ */
+
+use crate::auth::Auth;
+use crate::reportprocess;
+use crate::template;
+use reqwest::{header, Client, Method};
+use serde_json::{json, Value};
+use std::error::Error;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+/// 成功时返回 HTTP 状态码，不是 HTTP 的上报方式返回 None
+pub type ReportFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<u16>, Box<dyn Error>>> + 'a>>;
+
+/// 单次上报的超时：检测间隔的两倍，限制在 5 到 30 秒之间，避免一个卡住的目标拖住检测循环
+pub fn report_timeout(watch_time: i64) -> Duration {
+    Duration::from_secs(watch_time.saturating_mul(2).clamp(5, 30) as u64)
+}
+
+/// 上报目的地，同一份活动数据可以同时发往多个 Reporter
+pub trait Reporter {
+    /// 上报方式的名称，用于日志
+    fn name(&self) -> &str;
+
+    /// 上报失败时是否需要进入离线队列稍后重发
+    fn wants_queue(&self) -> bool {
+        false
+    }
+
+    fn report<'a>(&'a self, payload: &'a Value) -> ReportFuture<'a>;
+}
+
+/// Mix Space 云函数上报（原有行为）
+pub struct MixSpaceReporter {
+    pub api_url: String,
+    pub auth: Auth,
+    pub timeout: Duration,
+}
+
+impl Reporter for MixSpaceReporter {
+    fn name(&self) -> &str {
+        "mixspace"
+    }
+
+    fn wants_queue(&self) -> bool {
+        true
+    }
+
+    fn report<'a>(&'a self, payload: &'a Value) -> ReportFuture<'a> {
+        Box::pin(async move {
+            let status = reportprocess::send_payload(payload, &self.auth, &self.api_url, self.timeout).await?;
+            Ok(Some(status))
+        })
+    }
+}
+
+/// 通用 Webhook，可自定义请求方法、请求头和认证方式
+///
+/// 请求头中可以使用 `{{api_key}}` 占位符；payload 为字符串时按纯文本发送。
+pub struct WebhookReporter {
+    pub url: String,
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+    pub auth: Auth,
+    pub timeout: Duration,
+}
+
+impl Reporter for WebhookReporter {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn wants_queue(&self) -> bool {
+        true
+    }
+
+    fn report<'a>(&'a self, payload: &'a Value) -> ReportFuture<'a> {
+        Box::pin(async move {
+            let method = Method::from_bytes(self.method.to_uppercase().as_bytes())?;
+            let (content_type, body) = match payload {
+                Value::String(text) => ("text/plain; charset=utf-8", text.clone()),
+                other => ("application/json", other.to_string()),
+            };
+
+            let mut request = Client::builder()
+                .timeout(self.timeout)
+                .build()?
+                .request(method, &self.url)
+                .header(header::CONTENT_TYPE, content_type);
+            let context = json!({ "api_key": self.auth.key.expose() });
+            for (name, value) in &self.headers {
+                request = request.header(name.as_str(), template::render_str(value, &context));
+            }
+
+            let response = self
+                .auth
+                .apply(request, body.as_bytes())
+                .body(body)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(Some(response.status().as_u16()))
+        })
+    }
+}
+
+/// 以 JSON Lines 格式追加写入本地文件
+pub struct JsonLinesReporter {
+    pub path: PathBuf,
+}
+
+impl Reporter for JsonLinesReporter {
+    fn name(&self) -> &str {
+        "jsonl"
+    }
+
+    fn report<'a>(&'a self, payload: &'a Value) -> ReportFuture<'a> {
+        Box::pin(async move {
+            if let Some(parent) = self.path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await?;
+            file.write_all(format!("{}\n", payload).as_bytes()).await?;
+            Ok(None)
+        })
+    }
+}
+
+/// 输出到标准输出，方便接管道给其他工具
+pub struct StdoutReporter;
+
+impl Reporter for StdoutReporter {
+    fn name(&self) -> &str {
+        "stdout"
+    }
+
+    fn report<'a>(&'a self, payload: &'a Value) -> ReportFuture<'a> {
+        Box::pin(async move {
+            println!("{}", payload);
+            Ok(None)
+        })
+    }
+}
+
+/// 写入 Unix 套接字，每次上报一行 JSON
+pub struct UnixSocketReporter {
+    pub path: PathBuf,
+}
+
+impl Reporter for UnixSocketReporter {
+    fn name(&self) -> &str {
+        "unix"
+    }
+
+    fn wants_queue(&self) -> bool {
+        true
+    }
+
+    fn report<'a>(&'a self, payload: &'a Value) -> ReportFuture<'a> {
+        Box::pin(async move {
+            let mut stream = tokio::net::UnixStream::connect(&self.path).await?;
+            stream.write_all(format!("{}\n", payload).as_bytes()).await?;
+            stream.shutdown().await?;
+            Ok(None)
+        })
+    }
+}
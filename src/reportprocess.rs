@@ -5,31 +5,28 @@
  * @LastEditTime: 2023-10-11 07:56:54
  * @FilePath: /processforlinux/src/reportprocess.rs
  */
-use chrono::Utc;
+use crate::auth::Auth;
 use reqwest::{
     header::{self, HeaderValue},
     Client,
 };
-use serde_json::{self as json_self, json};
+use serde_json::{self as json_self, json, Value};
 use std::error::Error;
+use std::time::Duration;
+use tracing::debug;
 
 const USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36 uacq";
 const CONTENT_TYPE: &str = "application/json";
 
-pub async fn process_report(
+pub fn build_payload(
     process_name: &str,
     media_title: &str,
     media_artist: &str,
     media_thumbnail: &str,
     extend: &str,
-    api_key: &str,
-    api_url: &str,
-    watch_time: i64,
-    log_enable: bool,
-) -> Result<(), Box<dyn Error>> {
-    let timestamp = Utc::now().timestamp();
-
-    let payload = if media_title == "None" {
+    timestamp: i64,
+) -> Value {
+    if media_title == "None" {
         json!({
             "process": process_name,
             "timestamp": timestamp,
@@ -46,41 +43,33 @@ pub async fn process_report(
             },
             "extend": extend,
         })
-    };
+    }
+}
 
-    let client = Client::builder().build()?;
+pub async fn send_payload(
+    payload: &Value,
+    auth: &Auth,
+    api_url: &str,
+    timeout: Duration,
+) -> Result<u16, Box<dyn Error>> {
+    let client = Client::builder().timeout(timeout).build()?;
 
     let mut headers = header::HeaderMap::new();
     headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(CONTENT_TYPE));
     headers.insert(header::USER_AGENT, HeaderValue::from_static(USER_AGENT));
-    headers.insert(
-        "Authorization",
-        HeaderValue::from_str(&format!("{}", api_key))?,
-    );
 
-    let response = client
-        .post(api_url)
-        .headers(headers)
-        .body(json_self::to_string(&payload)?)
+    let body = json_self::to_string(payload)?;
+    let request = client.post(api_url).headers(headers);
+    let response = auth
+        .apply(request, body.as_bytes())
+        .body(body)
         .send()
         .await?
-        .text()
-        .await?;
-
-    if log_enable {
-        let utc_now = Utc::now();
-        let this_report_time = utc_now.format("%Y-%m-%d %H:%M:%S").to_string();
-        let next_watch_time = utc_now
-            .checked_add_signed(chrono::Duration::seconds(watch_time))
-            .unwrap()
-            .format("%Y-%m-%d %H:%M:%S");
+        .error_for_status()?;
+    let status = response.status().as_u16();
+    let response = response.text().await?;
 
-        println!("--------------------------------------------------");
-        println!("This Report Time: {}", this_report_time);
-        println!("Response: {}", &response);
-        println!("Next Watch Time : {}", next_watch_time);
-        println!("--------------------------------------------------");
-    }
+    debug!(status, response = %response, "Reported to Mix Space");
 
-    Ok(())
+    Ok(status)
 }
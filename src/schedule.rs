@@ -0,0 +1,304 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/schedule.rs
+ */
+
+use crate::config::ScheduleConfig;
+use chrono::{DateTime, Datelike, Local, Timelike, Utc};
+use chrono_tz::Tz;
+use std::str::FromStr;
+
+/// 星期的缩写和全称
+const DAY_NAMES: [(&str, &str); 7] = [
+    ("mon", "monday"),
+    ("tue", "tuesday"),
+    ("wed", "wednesday"),
+    ("thu", "thursday"),
+    ("fri", "friday"),
+    ("sat", "saturday"),
+    ("sun", "sunday"),
+];
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+/// 一周中的若干天，bit 0 为周一
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Days(u8);
+
+impl Days {
+    const ALL: Days = Days(0b111_1111);
+
+    fn contains(self, weekday: u32) -> bool {
+        self.0 & (1 << weekday) != 0
+    }
+}
+
+fn parse_day(name: &str) -> Result<u32, String> {
+    let name = name.trim().to_lowercase();
+    DAY_NAMES
+        .iter()
+        .position(|(short, full)| name == *short || name == *full)
+        .map(|index| index as u32)
+        .ok_or_else(|| format!("unknown weekday '{}'", name))
+}
+
+/// 解析 "mon-fri,sun" 这样的星期列表，"*" 表示每天
+impl FromStr for Days {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        if spec.trim() == "*" {
+            return Ok(Days::ALL);
+        }
+        let mut days = 0u8;
+        for part in spec.split(',') {
+            match part.split_once('-') {
+                Some((first, last)) => {
+                    let (first, last) = (parse_day(first)?, parse_day(last)?);
+                    // 允许 fri-mon 这样跨周末的范围
+                    let mut day = first;
+                    loop {
+                        days |= 1 << day;
+                        if day == last {
+                            break;
+                        }
+                        day = (day + 1) % 7;
+                    }
+                }
+                None => days |= 1 << parse_day(part)?,
+            }
+        }
+        Ok(Days(days))
+    }
+}
+
+/// 解析 HH:MM，返回从零点开始的分钟数，允许 24:00
+fn parse_time(time: &str) -> Result<u32, String> {
+    let invalid = || format!("invalid time '{}', expected HH:MM", time);
+    let (hour, minute) = time.trim().split_once(':').ok_or_else(invalid)?;
+    let (hour, minute): (u32, u32) = (
+        hour.parse().map_err(|_| invalid())?,
+        minute.parse().map_err(|_| invalid())?,
+    );
+    if minute >= 60 || hour * 60 + minute > MINUTES_PER_DAY {
+        return Err(invalid());
+    }
+    Ok(hour * 60 + minute)
+}
+
+/// 时间段，例如 "mon-fri 09:00-18:00"；省略星期表示每天
+///
+/// 结束时间不晚于开始时间时视为跨过午夜，例如 "fri 22:00-02:00" 覆盖周五晚上到周六凌晨
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeWindow {
+    days: Days,
+    start: u32,
+    end: u32,
+}
+
+impl TimeWindow {
+    fn contains(&self, weekday: u32, minute: u32) -> bool {
+        if self.start < self.end {
+            return self.days.contains(weekday) && self.start <= minute && minute < self.end;
+        }
+        // 跨午夜：开始当天的后半段，加上第二天的前半段
+        let previous_day = (weekday + 6) % 7;
+        (self.days.contains(weekday) && minute >= self.start)
+            || (self.days.contains(previous_day) && minute < self.end)
+    }
+}
+
+impl FromStr for TimeWindow {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let spec = spec.trim();
+        let (days, range) = match spec.rsplit_once(char::is_whitespace) {
+            Some((days, range)) => (days.parse()?, range),
+            None => (Days::ALL, spec),
+        };
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("invalid time range '{}', expected HH:MM-HH:MM", range))?;
+        Ok(TimeWindow {
+            days,
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+        })
+    }
+}
+
+/// 判断时间段所用的时区
+#[derive(Debug, Clone, PartialEq)]
+enum Zone {
+    Local,
+    Named(Tz),
+}
+
+/// 上报时间表：只在 windows 内上报（为空表示全天），quiet 内的时间除外
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    zone: Zone,
+    windows: Vec<TimeWindow>,
+    quiet: Vec<TimeWindow>,
+    /// 时间表外是否完全不上报（只在边界发送一次固定状态）
+    pub silent: bool,
+    /// 时间表外的固定状态
+    pub process: String,
+    pub extend: String,
+}
+
+impl Schedule {
+    pub fn from_config(config: &ScheduleConfig) -> Result<Self, String> {
+        let zone = match &config.timezone {
+            Some(name) => Zone::Named(
+                name.parse()
+                    .map_err(|_| format!("unknown time zone '{}'", name))?,
+            ),
+            None => Zone::Local,
+        };
+        let parse_all = |specs: &[String]| {
+            specs
+                .iter()
+                .map(|spec| spec.parse())
+                .collect::<Result<Vec<TimeWindow>, String>>()
+        };
+        let silent = match config.mode.to_lowercase().as_str() {
+            "silent" => true,
+            "placeholder" => false,
+            mode => {
+                return Err(format!(
+                    "unknown schedule mode '{}', expected placeholder or silent",
+                    mode
+                ))
+            }
+        };
+
+        Ok(Schedule {
+            zone,
+            windows: parse_all(&config.windows)?,
+            quiet: parse_all(&config.quiet)?,
+            silent,
+            process: config.process.clone(),
+            extend: config.extend.clone(),
+        })
+    }
+
+    /// 当前是否在上报时间内
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        let (weekday, minute) = match &self.zone {
+            Zone::Local => {
+                let now = now.with_timezone(&Local);
+                (now.weekday().num_days_from_monday(), now.hour() * 60 + now.minute())
+            }
+            Zone::Named(tz) => {
+                let now = now.with_timezone(tz);
+                (now.weekday().num_days_from_monday(), now.hour() * 60 + now.minute())
+            }
+        };
+
+        let in_window = self.windows.is_empty()
+            || self.windows.iter().any(|window| window.contains(weekday, minute));
+        in_window && !self.quiet.iter().any(|window| window.contains(weekday, minute))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const MON: u32 = 0;
+    const THU: u32 = 3;
+    const FRI: u32 = 4;
+    const SAT: u32 = 5;
+    const SUN: u32 = 6;
+
+    fn at(hour: u32, minute: u32) -> u32 {
+        hour * 60 + minute
+    }
+
+    #[test]
+    fn parses_day_lists() {
+        let days = |spec: &str| spec.parse::<Days>().map(|days| days.0);
+        assert_eq!(days("mon-fri"), Ok(0b001_1111));
+        assert_eq!(days("sat,sun"), Ok(0b110_0000));
+        assert_eq!(days("fri-mon"), Ok(0b111_0001));
+        assert_eq!(days("Monday, wed"), Ok(0b000_0101));
+        assert_eq!(days("*"), Ok(0b111_1111));
+        assert!(days("mon-funday").is_err());
+        // 只认缩写和全称，拼错的不能被当成某一天
+        for spec in ["monkey", "sunshine", "tues", "mo", "fri-satur"] {
+            assert!(days(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("09:30"), Ok(570));
+        assert_eq!(parse_time("24:00"), Ok(MINUTES_PER_DAY));
+        for time in ["24:01", "09:60", "9", "ab:cd"] {
+            assert!(parse_time(time).is_err(), "{}", time);
+        }
+    }
+
+    #[test]
+    fn window_within_a_day() {
+        let window: TimeWindow = "mon-fri 09:00-18:00".parse().unwrap();
+        assert!(window.contains(MON, at(9, 0)));
+        assert!(window.contains(FRI, at(17, 59)));
+        assert!(!window.contains(FRI, at(18, 0)));
+        assert!(!window.contains(MON, at(8, 59)));
+        assert!(!window.contains(SAT, at(12, 0)));
+    }
+
+    #[test]
+    fn overnight_window_continues_into_next_day() {
+        let window: TimeWindow = "fri 22:00-02:00".parse().unwrap();
+        assert!(window.contains(FRI, at(22, 0)));
+        assert!(window.contains(FRI, at(23, 59)));
+        assert!(window.contains(SAT, at(0, 0)));
+        assert!(window.contains(SAT, at(1, 59)));
+        assert!(!window.contains(SAT, at(2, 0)));
+        assert!(!window.contains(FRI, at(21, 59)));
+        // 开始于周四的那一晚不在范围内
+        assert!(!window.contains(FRI, at(1, 0)));
+        assert!(!window.contains(THU, at(23, 0)));
+        assert!(!window.contains(SAT, at(23, 0)));
+
+        // 跨过周日午夜
+        let window: TimeWindow = "sun 23:00-01:00".parse().unwrap();
+        assert!(window.contains(SUN, at(23, 30)));
+        assert!(window.contains(MON, at(0, 30)));
+    }
+
+    #[test]
+    fn quiet_hours_override_windows() {
+        let schedule = Schedule::from_config(&ScheduleConfig {
+            timezone: Some("Asia/Shanghai".to_string()),
+            windows: vec!["mon-fri 09:00-18:00".to_string()],
+            quiet: vec!["12:00-13:00".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        // 2026-10-19 是周一，上海比 UTC 快 8 小时
+        let utc = |day, hour, minute| Utc.with_ymd_and_hms(2026, 10, day, hour, minute, 0).unwrap();
+        assert!(schedule.is_active(utc(19, 2, 0)));
+        assert!(!schedule.is_active(utc(19, 4, 30)));
+        assert!(schedule.is_active(utc(19, 5, 0)));
+        assert!(!schedule.is_active(utc(19, 10, 0)));
+        assert!(!schedule.is_active(utc(18, 2, 0)));
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let config = |timezone: Option<&str>, mode: &str| ScheduleConfig {
+            timezone: timezone.map(str::to_string),
+            mode: mode.to_string(),
+            ..Default::default()
+        };
+        assert!(Schedule::from_config(&config(Some("Mars/Olympus"), "silent")).is_err());
+        assert!(Schedule::from_config(&config(None, "loud")).is_err());
+        assert!(Schedule::from_config(&config(None, "Placeholder")).is_ok_and(|schedule| !schedule.silent));
+    }
+}
@@ -0,0 +1,349 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/secret_store.rs
+ */
+
+use crate::auth::Secret;
+use dbus::arg::{RefArg, Variant};
+use dbus::blocking::{Connection, Proxy};
+use dbus::Path;
+use std::collections::HashMap;
+use std::error::Error;
+use std::time::Duration;
+
+mod constants {
+    pub const SERVICE_NAME: &str = "org.freedesktop.secrets";
+    pub const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+    pub const DEFAULT_COLLECTION: &str = "/org/freedesktop/secrets/aliases/default";
+    pub const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+    pub const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+    pub const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+    pub const LABEL_PROPERTY: &str = "org.freedesktop.Secret.Item.Label";
+    pub const ATTRIBUTES_PROPERTY: &str = "org.freedesktop.Secret.Item.Attributes";
+}
+
+/// Secret Service 中的 secret 结构：(session, parameters, value, content_type)
+type SecretStruct = (Path<'static>, Vec<u8>, Vec<u8>, String);
+
+/// 解析密钥引用
+///
+/// - `keyring:<service>/<account>` 从 Secret Service（GNOME Keyring / KWallet）读取
+/// - `file:<path>` 读取文件内容（去掉首尾空白）
+/// - `env:<VAR>` 读取环境变量
+/// - 其他值按明文密钥处理
+pub fn resolve(value: &str) -> Result<Secret, Box<dyn Error>> {
+    if let Some(reference) = value.strip_prefix("keyring:") {
+        let (service, account) = parse_keyring_reference(reference)?;
+        return lookup_keyring(&Connection::new_session()?, service, account);
+    }
+    if let Some(path) = value.strip_prefix("file:") {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read secret file {}: {}", path, e))?;
+        return Ok(Secret::new(content.trim()));
+    }
+    if let Some(var) = value.strip_prefix("env:") {
+        let content = std::env::var(var)
+            .map_err(|_| format!("Environment variable {} is not set", var))?;
+        return Ok(Secret::new(content));
+    }
+    Ok(Secret::new(value))
+}
+
+fn parse_keyring_reference(reference: &str) -> Result<(&str, &str), Box<dyn Error>> {
+    match reference.split_once('/') {
+        Some((service, account)) if !service.is_empty() && !account.is_empty() => {
+            Ok((service, account))
+        }
+        _ => Err(format!(
+            "Invalid keyring reference 'keyring:{}', expected keyring:<service>/<account>",
+            reference
+        )
+        .into()),
+    }
+}
+
+fn attributes<'a>(service: &'a str, account: &'a str) -> HashMap<&'static str, &'a str> {
+    HashMap::from([("service", service), ("account", account)])
+}
+
+fn service_proxy(connection: &Connection) -> Proxy<'_, &Connection> {
+    connection.with_proxy(
+        constants::SERVICE_NAME,
+        constants::SERVICE_PATH,
+        Duration::from_millis(5000),
+    )
+}
+
+/// 打开一个不加密的会话（连接只在本机 session bus 上）
+fn open_session(connection: &Connection) -> Result<Path<'static>, Box<dyn Error>> {
+    let (_, session): (Variant<Box<dyn RefArg>>, Path<'static>) = service_proxy(connection)
+        .method_call(
+            constants::SERVICE_INTERFACE,
+            "OpenSession",
+            ("plain", Variant("")),
+        )?;
+    Ok(session)
+}
+
+fn lookup_keyring(connection: &Connection, service: &str, account: &str) -> Result<Secret, Box<dyn Error>> {
+    let session = open_session(connection)?;
+
+    let (unlocked, locked): (Vec<Path<'static>>, Vec<Path<'static>>) = service_proxy(connection)
+        .method_call(
+            constants::SERVICE_INTERFACE,
+            "SearchItems",
+            (attributes(service, account),),
+        )?;
+
+    let item = match unlocked.into_iter().next() {
+        Some(item) => item,
+        None if !locked.is_empty() => {
+            // 尝试解锁；需要用户交互（prompt）时直接报错，由用户先解锁钥匙环
+            let (unlocked, prompt): (Vec<Path<'static>>, Path<'static>) = service_proxy(connection)
+                .method_call(constants::SERVICE_INTERFACE, "Unlock", (locked,))?;
+            if &*prompt != "/" || unlocked.is_empty() {
+                return Err(format!(
+                    "Keyring item {}/{} is locked, please unlock your keyring first",
+                    service, account
+                )
+                .into());
+            }
+            unlocked.into_iter().next().unwrap()
+        }
+        None => {
+            return Err(format!(
+                "No keyring item found for {}/{}, store one with `processforlinux set-key keyring:{}/{}`",
+                service, account, service, account
+            )
+            .into())
+        }
+    };
+
+    let (secret,): (SecretStruct,) = connection
+        .with_proxy(constants::SERVICE_NAME, item, Duration::from_millis(5000))
+        .method_call(constants::ITEM_INTERFACE, "GetSecret", (session,))?;
+    let value = String::from_utf8(secret.2)?;
+    Ok(Secret::new(value))
+}
+
+/// 把密钥存入默认钥匙环，已存在的同名条目会被替换
+pub fn store_keyring(reference: &str, secret: &Secret) -> Result<(), Box<dyn Error>> {
+    store_in(&Connection::new_session()?, reference, secret)
+}
+
+fn store_in(connection: &Connection, reference: &str, secret: &Secret) -> Result<(), Box<dyn Error>> {
+    let reference = reference.strip_prefix("keyring:").unwrap_or(reference);
+    let (service, account) = parse_keyring_reference(reference)?;
+
+    let session = open_session(connection)?;
+
+    let attributes: HashMap<String, String> = attributes(service, account)
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let mut properties: HashMap<&str, Variant<Box<dyn RefArg>>> = HashMap::new();
+    properties.insert(
+        constants::LABEL_PROPERTY,
+        Variant(Box::new(format!("processforlinux: {}/{}", service, account))),
+    );
+    properties.insert(constants::ATTRIBUTES_PROPERTY, Variant(Box::new(attributes)));
+
+    let secret: SecretStruct = (
+        session,
+        Vec::new(),
+        secret.expose().as_bytes().to_vec(),
+        "text/plain".to_string(),
+    );
+
+    let (_, prompt): (Path<'static>, Path<'static>) = connection
+        .with_proxy(
+            constants::SERVICE_NAME,
+            constants::DEFAULT_COLLECTION,
+            Duration::from_millis(5000),
+        )
+        .method_call(
+            constants::COLLECTION_INTERFACE,
+            "CreateItem",
+            (properties, secret, true),
+        )?;
+
+    if &*prompt != "/" {
+        return Err("The default keyring is locked, please unlock it and try again".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use dbus::blocking::stdintf::org_freedesktop_dbus::RequestNameReply;
+    use dbus::channel::{Channel, MatchingReceiver, Sender};
+    use dbus::message::MatchRule;
+    use dbus::Message;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// 私有的 session bus，测试结束时关闭
+    struct Bus {
+        daemon: Child,
+        address: String,
+    }
+
+    impl Bus {
+        fn start() -> Option<Bus> {
+            let mut daemon = Command::new("dbus-daemon")
+                .args(["--session", "--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn()
+                .ok()?;
+            let mut address = String::new();
+            BufReader::new(daemon.stdout.take()?).read_line(&mut address).ok()?;
+            Some(Bus {
+                daemon,
+                address: address.trim().to_string(),
+            })
+        }
+
+        fn connect(&self) -> Connection {
+            let mut channel = Channel::open_private(&self.address).unwrap();
+            channel.register().unwrap();
+            Connection::from(channel)
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+        }
+    }
+
+    type Properties = HashMap<String, Variant<Box<dyn RefArg>>>;
+
+    /// 条目：属性和密钥
+    type Items = Arc<Mutex<Vec<(HashMap<String, String>, Vec<u8>)>>>;
+
+    fn item_path(index: usize) -> Path<'static> {
+        Path::new(format!("/org/freedesktop/secrets/collection/login/{}", index)).unwrap()
+    }
+
+    /// 只实现用得到的方法的 Secret Service
+    fn handle(message: &Message, items: &Items) -> Message {
+        let mut items = items.lock().unwrap();
+        let member = message.member().unwrap();
+        match &*member {
+            "OpenSession" => message
+                .method_return()
+                .append2(Variant(String::new()), Path::from("/org/freedesktop/secrets/session/1")),
+            "SearchItems" => {
+                let wanted: HashMap<String, String> = message.read1().unwrap();
+                let found: Vec<Path> = (0..items.len())
+                    .filter(|index| items[*index].0 == wanted)
+                    .map(item_path)
+                    .collect();
+                message.method_return().append2(found, Vec::<Path>::new())
+            }
+            "CreateItem" => {
+                let (properties, secret, replace): (Properties, SecretStruct, bool) = message.read3().unwrap();
+                // 属性字典按 键、值、键、值 的顺序展开
+                let values: Vec<String> = properties[constants::ATTRIBUTES_PROPERTY]
+                    .0
+                    .as_iter()
+                    .unwrap()
+                    .map(|value| value.as_str().unwrap().to_string())
+                    .collect();
+                let attributes: HashMap<String, String> = values
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+                let index = match items.iter().position(|(existing, _)| replace && *existing == attributes) {
+                    Some(index) => {
+                        items[index].1 = secret.2;
+                        index
+                    }
+                    None => {
+                        items.push((attributes, secret.2));
+                        items.len() - 1
+                    }
+                };
+                message.method_return().append2(item_path(index), Path::from("/"))
+            }
+            "GetSecret" => {
+                let session: Path = message.read1().unwrap();
+                let path = message.path().unwrap();
+                let index: usize = path.rsplit('/').next().unwrap().parse().unwrap();
+                let secret: SecretStruct = (session, Vec::new(), items[index].1.clone(), "text/plain".to_string());
+                message.method_return().append1(secret)
+            }
+            _ => message.error(&"org.freedesktop.DBus.Error.UnknownMethod".into(), c"unknown method"),
+        }
+    }
+
+    #[test]
+    fn stores_and_resolves_keyring_items() {
+        let Some(bus) = Bus::start() else {
+            eprintln!("dbus-daemon is not available, skipping");
+            return;
+        };
+
+        let items = Items::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let service = bus.connect();
+        let reply = service
+            .request_name(constants::SERVICE_NAME, false, true, true)
+            .unwrap();
+        assert_eq!(reply, RequestNameReply::PrimaryOwner);
+        let server = {
+            let (items, stop) = (items.clone(), stop.clone());
+            std::thread::spawn(move || {
+                service.start_receive(
+                    MatchRule::new_method_call(),
+                    Box::new(move |message, connection| {
+                        let _ = connection.send(handle(&message, &items));
+                        true
+                    }),
+                );
+                while !stop.load(Ordering::SeqCst) {
+                    service.process(Duration::from_millis(50)).unwrap();
+                }
+            })
+        };
+
+        let client = bus.connect();
+        store_in(&client, "keyring:blog/token", &Secret::new("first")).unwrap();
+        // 同名条目被替换
+        store_in(&client, "blog/token", &Secret::new("second")).unwrap();
+        store_in(&client, "keyring:other/token", &Secret::new("other")).unwrap();
+        assert_eq!(items.lock().unwrap().len(), 2);
+
+        assert_eq!(lookup_keyring(&client, "blog", "token").unwrap().expose(), "second");
+        assert_eq!(lookup_keyring(&client, "other", "token").unwrap().expose(), "other");
+        let missing = lookup_keyring(&client, "blog", "missing").unwrap_err().to_string();
+        assert!(missing.contains("set-key keyring:blog/missing"), "{}", missing);
+
+        stop.store(true, Ordering::SeqCst);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn rejects_malformed_keyring_references() {
+        for reference in ["keyring:blog", "keyring:/token", "keyring:blog/"] {
+            assert!(resolve(reference).is_err(), "{}", reference);
+        }
+    }
+
+    #[test]
+    fn resolves_plain_file_and_env_values() {
+        assert_eq!(resolve("plain-key").unwrap().expose(), "plain-key");
+        let dir = TempDir::new("secret");
+        let path = dir.join("key");
+        std::fs::write(&path, "  from-file\n").unwrap();
+        assert_eq!(resolve(&format!("file:{}", path.display())).unwrap().expose(), "from-file");
+        assert!(resolve("env:PROCESSFORLINUX_TEST_UNSET_SECRET").is_err());
+    }
+}
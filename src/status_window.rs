@@ -4,13 +4,18 @@
  * @FilePath: /processforlinux/src/status_window.rs
  */
 
-use chrono::{DateTime, Utc};
+use crate::control::{self, ControlSender, Request};
+use crate::pause;
+use chrono::{DateTime, Local, Utc};
 use egui_overlay::EguiOverlay;
-use std::sync::mpsc;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::time::Duration;
+use tracing::{debug, warn};
 
-/// 应用运行状态数据
-#[derive(Debug, Clone)]
+/// 应用运行状态数据，也是控制套接字 status 命令返回的内容
+#[derive(Debug, Clone, Serialize)]
 pub struct AppStatus {
     pub session_type: String,
     pub current_window: String,
@@ -19,13 +24,22 @@ pub struct AppStatus {
     pub watch_interval: i64, // 检测间隔（秒）
     pub media_title: String,
     pub media_artist: String,
+    #[allow(dead_code)] // 暂未在悬浮窗中显示封面
     pub media_thumbnail: String,
     pub stats: RunningStats,
+    pub targets: Vec<TargetHealth>,
+    /// 重新加载配置失败时的错误，此时仍在使用旧配置
+    pub config_error: Option<String>,
+    /// 是否暂停了上报
+    pub paused: bool,
+    /// 暂停的自动恢复时间，为空表示一直暂停到手动恢复
+    pub paused_until: Option<DateTime<Utc>>,
     pub last_error: Option<String>,
 }
 
 /// 窗口获取状态
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "state", content = "error", rename_all = "snake_case")]
 pub enum WindowStatus {
     Success,
     Failed(String),
@@ -33,7 +47,7 @@ pub enum WindowStatus {
 }
 
 /// 运行统计信息
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RunningStats {
     pub start_time: DateTime<Utc>,
     pub success_count: u64,
@@ -41,6 +55,16 @@ pub struct RunningStats {
     pub total_checks: u64,
 }
 
+/// 单个上报目标的健康状态
+#[derive(Debug, Clone, Serialize)]
+pub struct TargetHealth {
+    pub name: String,
+    pub success_count: u64,
+    pub failure_count: u64,
+    pub queued: usize,
+    pub last_error: Option<String>,
+}
+
 impl Default for AppStatus {
     fn default() -> Self {
         Self {
@@ -58,27 +82,71 @@ impl Default for AppStatus {
                 failure_count: 0,
                 total_checks: 0,
             },
+            targets: Vec::new(),
+            config_error: None,
+            paused: false,
+            paused_until: None,
             last_error: None,
         }
     }
 }
 
+/// 监控循环持有的悬浮窗句柄：发送状态，再次启动时重新显示悬浮窗，悬浮窗关闭时退出
+pub struct OverlayHandle {
+    pub status: mpsc::Sender<AppStatus>,
+    pub visible: Arc<AtomicBool>,
+    pub closed: tokio::sync::watch::Receiver<bool>,
+}
+
 /// 状态窗口应用
 pub struct StatusWindow {
     pub status: AppStatus,
     pub receiver: mpsc::Receiver<AppStatus>,
+    /// 暂停/恢复等操作发给监控循环
+    control: ControlSender,
+    /// 点击 × 后隐藏，再次启动程序时重新显示
+    visible: Arc<AtomicBool>,
+    /// 监控循环退出后置位，悬浮窗随之关闭
+    close: Arc<AtomicBool>,
     font_loaded: bool,
 }
 
 impl StatusWindow {
-    pub fn new(receiver: mpsc::Receiver<AppStatus>) -> Self {
+    pub fn new(
+        receiver: mpsc::Receiver<AppStatus>,
+        control: ControlSender,
+        visible: Arc<AtomicBool>,
+        close: Arc<AtomicBool>,
+    ) -> Self {
         Self {
             status: AppStatus::default(),
             receiver,
+            control,
+            visible,
+            close,
             font_loaded: false,
         }
     }
 
+    /// 发送请求，并先在本地更新暂停状态，不必等下一次状态更新
+    fn send_request(&mut self, request: Request) {
+        match &request {
+            Request::Pause { minutes } => {
+                let Ok(until) = pause::resume_time(Utc::now(), *minutes) else {
+                    return;
+                };
+                self.status.paused = true;
+                self.status.paused_until = until;
+            }
+            Request::Resume => {
+                self.status.paused = false;
+                self.status.paused_until = None;
+            }
+            _ => {}
+        }
+        control::send_detached(&self.control, request);
+    }
+
     fn format_duration(&self, duration: Duration) -> String {
         let secs = duration.as_secs();
         if secs < 60 {
@@ -148,12 +216,12 @@ impl StatusWindow {
 
                 ctx.set_fonts(fonts);
                 self.font_loaded = true;
-                eprintln!("成功加载中文字体: {}", font_path);
+                debug!("Loaded CJK font {}", font_path);
                 return;
             }
         }
 
-        eprintln!("警告: 未找到中文字体，中文显示可能不正常");
+        warn!("No CJK font found, Chinese text may not render correctly");
         self.font_loaded = true;
     }
 }
@@ -165,6 +233,12 @@ impl EguiOverlay for StatusWindow {
         _default_gfx_backend: &mut egui_overlay::egui_render_three_d::ThreeDBackend,
         glfw_backend: &mut egui_overlay::egui_window_glfw_passthrough::GlfwBackend,
     ) {
+        // 监控循环已经退出，结束事件循环
+        if self.close.load(Ordering::Relaxed) {
+            glfw_backend.window.set_should_close(true);
+            return;
+        }
+
         // 设置中文字体（仅第一次调用时）
         self.setup_fonts(egui_context);
 
@@ -173,6 +247,13 @@ impl EguiOverlay for StatusWindow {
             self.status = new_status;
         }
 
+        // 隐藏时不绘制，鼠标全部穿透，定时检查是否需要重新显示
+        if !self.visible.load(Ordering::Relaxed) {
+            glfw_backend.set_passthrough(true);
+            egui_context.request_repaint_after(Duration::from_millis(500));
+            return;
+        }
+
         // 使用 Area 而不是 Window，这样可以自由拖拽
         egui::Area::new(egui::Id::new("status_area"))
             .movable(true)
@@ -194,10 +275,66 @@ impl EguiOverlay for StatusWindow {
                             .size(10.0)
                             .color(egui::Color32::from_rgba_premultiplied(100, 116, 139, 200))
                     );
+                    // Area 没有固定宽度，right_to_left 会撑满屏幕，按钮直接跟在标题后面
+                    let hide = ui
+                        .small_button(egui::RichText::new("×").size(9.0))
+                        .on_hover_text("隐藏，再次启动程序时重新显示");
+                    if hide.clicked() {
+                        self.visible.store(false, Ordering::Relaxed);
+                    }
                 });
 
                 ui.add_space(6.0);
 
+                // 暂停状态：暂停时醒目提示，否则显示暂停按钮
+                if self.status.paused {
+                    let mut resume = false;
+                    egui::Frame::none()
+                        .fill(egui::Color32::from_rgba_premultiplied(120, 80, 10, 200))
+                        .rounding(egui::Rounding::same(4.0))
+                        .inner_margin(egui::Margin::same(6.0))
+                        .show(ui, |ui| {
+                            ui.horizontal(|ui| {
+                                ui.spacing_mut().item_spacing.x = 6.0;
+                                let until = match self.status.paused_until {
+                                    Some(until) => format!("至 {}", until.with_timezone(&Local).format("%H:%M")),
+                                    None => "手动恢复".to_string(),
+                                };
+                                ui.label(
+                                    egui::RichText::new(format!("⏸ 已暂停上报 · {}", until))
+                                        .size(10.0)
+                                        .strong()
+                                        .color(egui::Color32::from_rgba_premultiplied(253, 224, 71, 255))
+                                );
+                                resume = ui
+                                    .small_button(egui::RichText::new("继续").size(9.0))
+                                    .clicked();
+                            });
+                        });
+                    if resume {
+                        self.send_request(Request::Resume);
+                    }
+                    ui.add_space(4.0);
+                } else {
+                    let mut pause_minutes = None;
+                    ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing.x = 4.0;
+                        ui.label(
+                            egui::RichText::new("⏸")
+                                .size(9.0)
+                                .color(egui::Color32::from_rgba_premultiplied(100, 116, 139, 200))
+                        );
+                        for (label, minutes) in [("15分", Some(15)), ("1小时", Some(60)), ("一直", None)] {
+                            if ui.small_button(egui::RichText::new(label).size(8.0)).clicked() {
+                                pause_minutes = Some(minutes);
+                            }
+                        }
+                    });
+                    if let Some(minutes) = pause_minutes {
+                        self.send_request(Request::Pause { minutes });
+                    }
+                }
+
                 // 第一行：会话类型 + 状态
                 ui.horizontal(|ui| {
                     ui.spacing_mut().item_spacing.x = 8.0;
@@ -337,6 +474,74 @@ impl EguiOverlay for StatusWindow {
                     }
                 }
 
+                // 配置重新加载失败
+                if let Some(error) = &self.status.config_error {
+                    ui.add_space(4.0);
+                    ui.separator();
+                    ui.add_space(4.0);
+
+                    ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing.x = 4.0;
+                        ui.label(
+                            egui::RichText::new("⚠")
+                                .size(9.0)
+                                .color(egui::Color32::from_rgba_premultiplied(252, 165, 165, 255))
+                        );
+                        ui.label(
+                            egui::RichText::new("配置有误，仍使用旧配置")
+                                .size(8.0)
+                                .color(egui::Color32::from_rgba_premultiplied(252, 165, 165, 255))
+                        );
+                    })
+                    .response
+                    .on_hover_text(error.clone());
+                }
+
+                // 上报目标健康状态
+                if !self.status.targets.is_empty() {
+                    ui.add_space(4.0);
+                    ui.separator();
+                    ui.add_space(4.0);
+
+                    for target in &self.status.targets {
+                        ui.horizontal(|ui| {
+                            ui.spacing_mut().item_spacing.x = 6.0;
+                            let dot_color = if target.last_error.is_some() {
+                                egui::Color32::from_rgba_premultiplied(252, 165, 165, 220)
+                            } else if target.success_count > 0 {
+                                egui::Color32::from_rgba_premultiplied(134, 239, 172, 220)
+                            } else {
+                                egui::Color32::from_rgba_premultiplied(100, 116, 139, 200)
+                            };
+                            ui.label(egui::RichText::new("●").size(8.0).color(dot_color));
+                            ui.label(
+                                egui::RichText::new(&target.name)
+                                    .size(8.0)
+                                    .color(egui::Color32::from_rgba_premultiplied(203, 213, 225, 255))
+                            );
+                            ui.label(
+                                egui::RichText::new(format!("✓ {}", target.success_count))
+                                    .size(8.0)
+                                    .color(egui::Color32::from_rgba_premultiplied(134, 239, 172, 255))
+                            );
+                            ui.label(
+                                egui::RichText::new(format!("✗ {}", target.failure_count))
+                                    .size(8.0)
+                                    .color(egui::Color32::from_rgba_premultiplied(252, 165, 165, 255))
+                            );
+                            if target.queued > 0 {
+                                ui.label(
+                                    egui::RichText::new(format!("⏳ {}", target.queued))
+                                        .size(8.0)
+                                        .color(egui::Color32::from_rgba_premultiplied(253, 224, 71, 220))
+                                );
+                            }
+                        })
+                        .response
+                        .on_hover_text(target.last_error.clone().unwrap_or_else(|| "正常".to_string()));
+                    }
+                }
+
                 // 统计信息（可折叠）
                 ui.add_space(4.0);
                 ui.separator();
@@ -454,8 +659,13 @@ impl EguiOverlay for StatusWindow {
     }
 }
 
-/// 启动状态窗口
-pub fn run_status_window(receiver: mpsc::Receiver<AppStatus>) {
+/// 启动状态窗口，窗口关闭或 close 置位后返回
+pub fn run_status_window(
+    receiver: mpsc::Receiver<AppStatus>,
+    control: ControlSender,
+    visible: Arc<AtomicBool>,
+    close: Arc<AtomicBool>,
+) {
     use egui_overlay::egui_window_glfw_passthrough::{GlfwBackend, GlfwConfig};
     use egui_overlay::egui_render_three_d::ThreeDBackend;
 
@@ -495,7 +705,7 @@ pub fn run_status_window(receiver: mpsc::Receiver<AppStatus>) {
     );
 
     let overlap_app = egui_overlay::OverlayApp {
-        user_data: StatusWindow::new(receiver),
+        user_data: StatusWindow::new(receiver, control, visible, close),
         egui_context: Default::default(),
         default_gfx_backend,
         glfw_backend,
@@ -0,0 +1,202 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/systemd.rs
+ */
+
+use crate::app_dirs;
+use crate::get_env_file;
+use clap::ArgMatches;
+use sd_notify::NotifyState;
+use std::error::Error;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+use tracing::warn;
+
+const UNIT_NAME: &str = "processforlinux.service";
+
+/// 写入单元的图形会话环境变量，systemd --user 默认拿不到这些
+const SESSION_VARS: &[&str] = &[
+    "WAYLAND_DISPLAY",
+    "DISPLAY",
+    "DBUS_SESSION_BUS_ADDRESS",
+    "XDG_SESSION_TYPE",
+    "XDG_CURRENT_DESKTOP",
+    "DESKTOP_SESSION",
+    "KDE_SESSION_VERSION",
+];
+
+/// 看门狗超时，留出一次卡住的 HTTP 请求的余量
+const WATCHDOG_SEC: u64 = 60;
+
+/// 用户单元的位置：$XDG_CONFIG_HOME/systemd/user/processforlinux.service
+fn unit_path() -> PathBuf {
+    app_dirs::systemd_user_dir().join(UNIT_NAME)
+}
+
+/// systemd 单元文件中的带引号参数：转义 \ 和 "，% 和 $ 需要写两次
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{}\"", escaped)
+}
+
+fn exec_start(matches: &ArgMatches) -> Result<String, Box<dyn Error>> {
+    let args = get_env_file::run_args(matches)?;
+    Ok(args.iter().map(|arg| quote(arg)).collect::<Vec<_>>().join(" "))
+}
+
+fn unit(matches: &ArgMatches) -> Result<String, Box<dyn Error>> {
+    let mut environment = String::new();
+    for var in SESSION_VARS {
+        if let Ok(value) = std::env::var(var) {
+            environment.push_str(&format!("Environment={}\n", quote(&format!("{}={}", var, value))));
+        }
+    }
+
+    Ok(format!(
+        "# Generated by `processforlinux install-service`, run it again after changing the session
+[Unit]
+Description=Process Report For Linux
+PartOf=graphical-session.target
+After=graphical-session.target
+
+[Service]
+Type=notify
+NotifyAccess=main
+ExecStart={}
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=on-failure
+RestartSec=5
+WatchdogSec={}
+{}
+[Install]
+WantedBy=graphical-session.target
+",
+        exec_start(matches)?,
+        WATCHDOG_SEC,
+        environment
+    ))
+}
+
+fn systemctl(args: &[&str]) -> Result<(), Box<dyn Error>> {
+    let status = Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .status()
+        .map_err(|e| format!("Failed to run systemctl: {}", e))?;
+    if !status.success() {
+        return Err(format!("`systemctl --user {}` failed with {}", args.join(" "), status).into());
+    }
+    Ok(())
+}
+
+/// install-service 子命令：生成 systemd 用户单元并启用
+pub fn install(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    // 先确认配置有效，避免安装一个启动即失败的服务
+    get_env_file::get_env_file(matches).map_err(|e| format!("Invalid config: {}", e))?;
+    for var in ["DBUS_SESSION_BUS_ADDRESS", "XDG_SESSION_TYPE"] {
+        if std::env::var_os(var).is_none() {
+            eprintln!("Warning: {} is not set, run install-service from your desktop session", var);
+        }
+    }
+
+    let path = unit_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, unit(matches)?)?;
+    println!("Wrote {}", path.display());
+
+    systemctl(&["daemon-reload"])?;
+    if matches.get_flag("no-enable") {
+        println!("Start it with `systemctl --user enable --now {}`", UNIT_NAME);
+        return Ok(());
+    }
+    // 已经在运行时重启，让新的单元生效
+    systemctl(&["enable", UNIT_NAME])?;
+    systemctl(&["restart", UNIT_NAME])?;
+    println!("Enabled and started {}", UNIT_NAME);
+    Ok(())
+}
+
+/// uninstall-service 子命令：停用并删除 systemd 用户单元
+pub fn uninstall() -> Result<(), Box<dyn Error>> {
+    let path = unit_path();
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()).into());
+    }
+    if let Err(e) = systemctl(&["disable", "--now", UNIT_NAME]) {
+        eprintln!("Warning: {}", e);
+    }
+    std::fs::remove_file(&path)?;
+    println!("Removed {}", path.display());
+    systemctl(&["daemon-reload"])
+}
+
+/// 向 systemd 报告状态；不是由 systemd 以 Type=notify 启动时（没有 NOTIFY_SOCKET）什么都不做
+pub struct Notifier {
+    /// 看门狗间隔的一半，没有启用看门狗时为空
+    watchdog: Option<Duration>,
+    status: String,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        let mut usec = 0;
+        let watchdog = sd_notify::watchdog_enabled(false, &mut usec).then(|| Duration::from_micros(usec / 2));
+        Notifier {
+            watchdog,
+            status: String::new(),
+        }
+    }
+
+    fn notify(&self, state: &[NotifyState]) {
+        if let Err(e) = sd_notify::notify(false, state) {
+            warn!("Failed to notify systemd: {}", e);
+        }
+    }
+
+    pub fn ready(&self) {
+        self.notify(&[NotifyState::Ready]);
+    }
+
+    pub fn stopping(&self) {
+        self.notify(&[NotifyState::Stopping]);
+    }
+
+    /// 只在状态变化时发送，systemctl status 中显示
+    pub fn status(&mut self, status: String) {
+        if status != self.status {
+            self.notify(&[NotifyState::Status(&status)]);
+            self.status = status;
+        }
+    }
+
+    /// 每轮检测后调用，run_loop 卡住时 systemd 会在 WatchdogSec 后重启服务
+    pub fn watchdog(&self) {
+        if self.watchdog.is_some() {
+            self.notify(&[NotifyState::Watchdog]);
+        }
+    }
+
+    /// 等待 duration，期间按看门狗间隔继续报告；检测间隔比看门狗超时还长时也不会被误杀
+    pub async fn sleep(&self, duration: Duration) {
+        let Some(interval) = self.watchdog else {
+            return tokio::time::sleep(duration).await;
+        };
+        let deadline = tokio::time::Instant::now() + duration;
+        loop {
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return;
+            }
+            tokio::time::sleep((deadline - now).min(interval)).await;
+            self.watchdog();
+        }
+    }
+}
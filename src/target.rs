@@ -0,0 +1,452 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/target.rs
+ */
+
+use crate::activity::Activity;
+use crate::app_rules::AppRules;
+use crate::config::TargetConfig;
+use crate::get_media::MediaMetadata;
+use crate::metrics::TargetMetrics;
+use crate::offline_queue::OfflineQueue;
+use crate::reporter::{self, Reporter};
+use crate::reportprocess;
+use crate::schedule::Schedule;
+use crate::status_window::TargetHealth;
+use crate::template;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// 重试退避的上限（秒）
+const MAX_BACKOFF_SECS: i64 = 300;
+
+/// 一个上报目标：上报方式 + 独立的离线队列、重试状态和统计
+pub struct Target {
+    reporter: Box<dyn Reporter>,
+    queue: Option<OfflineQueue>,
+    media_enable: bool,
+    extend_enable: bool,
+    exclude: Vec<String>,
+    rules: AppRules,
+    template: Option<Value>,
+    schedule: Option<Schedule>,
+    /// 上一次上报时是否在时间表内，用于发现边界
+    in_schedule: Option<bool>,
+    watch_time: i64,
+    /// 单次发送的超时，超时算作失败，不会拖住其他目标和检测循环
+    timeout: Duration,
+    consecutive_failures: u32,
+    retry_at: Option<DateTime<Utc>>,
+    pub health: TargetHealth,
+    pub metrics: TargetMetrics,
+}
+
+impl Target {
+    pub fn new(
+        config: &TargetConfig,
+        global_exclude: &[String],
+        reporter: Box<dyn Reporter>,
+        queue: Option<OfflineQueue>,
+        watch_time: i64,
+    ) -> Self {
+        let queued = queue.as_ref().map_or(0, |queue| queue.len());
+        Target {
+            reporter,
+            queue,
+            media_enable: config.media_enable,
+            extend_enable: config.extend_enable,
+            // 全局规则和目标自身的过滤列表同时生效
+            exclude: global_exclude.iter().chain(&config.exclude).cloned().collect(),
+            rules: config.resolved_rules.clone(),
+            template: config.template.clone(),
+            schedule: config.resolved_schedule.clone(),
+            in_schedule: None,
+            watch_time,
+            timeout: reporter::report_timeout(watch_time),
+            consecutive_failures: 0,
+            retry_at: None,
+            health: TargetHealth {
+                name: config.name.clone(),
+                success_count: 0,
+                failure_count: 0,
+                queued,
+                last_error: None,
+            },
+            metrics: TargetMetrics::default(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.health.name
+    }
+
+    /// 重新加载配置后沿用旧目标的统计、指标和最近的错误；上报方式没变时还沿用退避和时间表状态，
+    /// 避免每次重新加载都立即重试失败的目标，或者向有时间表的目标多发一次边界上报
+    pub fn carry_over(&mut self, previous: &Target) {
+        self.health.success_count = previous.health.success_count;
+        self.health.failure_count = previous.health.failure_count;
+        self.health.last_error = previous.health.last_error.clone();
+        self.metrics = previous.metrics.clone();
+        if self.reporter.name() == previous.reporter.name() {
+            self.in_schedule = previous.in_schedule;
+            self.consecutive_failures = previous.consecutive_failures;
+            self.retry_at = previous.retry_at;
+        }
+    }
+
+    /// 是否刚好跨过了时间表的边界，此时即使活动没变也要上报
+    pub fn schedule_changed(&self, now: DateTime<Utc>) -> bool {
+        self.schedule
+            .as_ref()
+            .is_some_and(|schedule| self.in_schedule != Some(schedule.is_active(now)))
+    }
+
+    /// 按该目标的 payload 选项和隐私过滤生成上报内容
+    fn build_payload(&self, activity: &Activity) -> Value {
+        let mut activity = activity.clone();
+        let excluded = self
+            .exclude
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(&activity.process));
+        if excluded {
+            activity.process.clear();
+            activity.extend.clear();
+        }
+        if !self.extend_enable {
+            activity.extend.clear();
+        }
+        if !self.media_enable {
+            activity.media = None;
+        }
+
+        if let Some(template) = &self.template {
+            return template::render(template, &activity.to_json());
+        }
+
+        // 没有模板时使用 Mix Space 的格式，标题为 "None" 时不附带 media 字段
+        let media = activity.media.clone().unwrap_or(MediaMetadata {
+            title: Some("None".to_string()),
+            ..Default::default()
+        });
+        reportprocess::build_payload(
+            &activity.process,
+            &media.title.unwrap_or_default(),
+            &media.artist.unwrap_or_default(),
+            &media.thumbnail.unwrap_or_default(),
+            &activity.extend,
+            activity.timestamp,
+        )
+    }
+
+    /// 按时间表和应用规则决定实际发送的内容，Err 为这次不发送的原因
+    ///
+    /// in_schedule 为当前是否在时间表内，crossed 表示刚跨过时间表的边界
+    fn resolve(&self, activity: &Activity, in_schedule: Option<bool>, crossed: bool) -> Result<Value, String> {
+        // 时间表外改为固定状态；silent 模式下只在离开时间表的边界发送一次
+        if let (Some(schedule), Some(false)) = (&self.schedule, in_schedule) {
+            if schedule.silent && !crossed {
+                return Err("outside schedule".to_string());
+            }
+            return Ok(self.build_payload(&Activity {
+                process: schedule.process.clone(),
+                extend: schedule.extend.clone(),
+                media: None,
+                timestamp: activity.timestamp,
+                window: None,
+            }));
+        }
+
+        self.rules.apply(activity).map(|activity| self.build_payload(&activity))
+    }
+
+    /// dry-run：返回现在上报时会发送的内容，不改变任何状态
+    pub fn preview(&self, activity: &Activity) -> Result<Value, String> {
+        let in_schedule = self.schedule.as_ref().map(|schedule| schedule.is_active(Utc::now()));
+        self.resolve(activity, in_schedule, true)
+    }
+
+    #[tracing::instrument(name = "report", skip_all, fields(target = %self.health.name))]
+    pub async fn report(&mut self, activity: &Activity) {
+        let in_schedule = self.schedule.as_ref().map(|schedule| schedule.is_active(Utc::now()));
+        let crossed = self.in_schedule != in_schedule;
+        self.in_schedule = in_schedule;
+        let payload = match self.resolve(activity, in_schedule, crossed) {
+            Ok(payload) => payload,
+            Err(reason) => {
+                info!("Not reporting: {}", reason);
+                return;
+            }
+        };
+
+        // 退避期间不访问网络，直接排进队列，等退避结束后统一重发
+        let backing_off = self.retry_at.is_some_and(|retry_at| Utc::now() < retry_at);
+        if backing_off {
+            if let Some(queue) = self.queue.as_mut() {
+                if let Err(e) = queue.push(payload) {
+                    error!("Failed to queue report: {}", e);
+                }
+                self.health.queued = queue.len();
+                return;
+            }
+        }
+
+        match self.deliver(payload).await {
+            Ok(()) => {
+                self.health.success_count += 1;
+                self.metrics.last_success = Some(Utc::now());
+                self.health.last_error = None;
+                self.consecutive_failures = 0;
+                self.retry_at = None;
+            }
+            Err(e) => {
+                warn!(reporter = self.reporter.name(), "Failed to report: {}", e);
+                self.health.failure_count += 1;
+                self.health.last_error = Some(e.to_string());
+                self.consecutive_failures += 1;
+
+                let backoff = self
+                    .watch_time
+                    .max(1)
+                    .saturating_mul(1 << self.consecutive_failures.min(10))
+                    .min(MAX_BACKOFF_SECS);
+                self.retry_at = Some(Utc::now() + chrono::Duration::seconds(backoff));
+            }
+        }
+        self.health.queued = self.queue.as_ref().map_or(0, |queue| queue.len());
+    }
+
+    /// 退出前发送离线状态，不受时间表和应用规则限制；先重发离线队列，
+    /// 发送失败时留在队列里，下次启动后按顺序重发
+    #[tracing::instrument(name = "offline", skip_all, fields(target = %self.health.name))]
+    pub async fn report_offline(&mut self, activity: &Activity) {
+        let payload = self.build_payload(activity);
+        match self.deliver(payload).await {
+            Ok(()) => info!("Sent offline report"),
+            Err(e) => warn!(reporter = self.reporter.name(), "Failed to send offline report: {}", e),
+        }
+        self.health.queued = self.queue.as_ref().map_or(0, |queue| queue.len());
+    }
+
+    async fn deliver(&mut self, payload: Value) -> Result<(), Box<dyn Error>> {
+        if self.queue.is_none() {
+            return self.send(&payload).await;
+        }
+
+        // 先按顺序重发积压的上报；只要有一条失败，新的上报也排到队尾，保证时间线有序
+        while let Some(pending) = self.queue.as_ref().and_then(|queue| queue.front()).map(|entry| entry.payload.clone()) {
+            if let Err(e) = self.send(&pending).await {
+                return Err(self.enqueue(payload, e));
+            }
+            if let Some(queue) = self.queue.as_mut() {
+                queue.pop_front()?;
+                if queue.is_empty() {
+                    info!("Offline queue flushed");
+                }
+            }
+        }
+
+        if let Err(e) = self.send(&payload).await {
+            return Err(self.enqueue(payload, e));
+        }
+        Ok(())
+    }
+
+    /// 发送一条，记录耗时和 HTTP 状态码
+    async fn send(&mut self, payload: &Value) -> Result<(), Box<dyn Error>> {
+        let started = Instant::now();
+        let result = tokio::time::timeout(self.timeout, self.reporter.report(payload))
+            .await
+            .unwrap_or_else(|_| Err(format!("timed out after {}s", self.timeout.as_secs_f64()).into()));
+        self.metrics.latency.observe(started.elapsed().as_secs_f64());
+
+        // 失败时状态码在 reqwest 的错误里
+        let status = match &result {
+            Ok(status) => *status,
+            Err(e) => e
+                .downcast_ref::<reqwest::Error>()
+                .and_then(|e| e.status())
+                .map(|status| status.as_u16()),
+        };
+        if let Some(status) = status {
+            *self.metrics.responses.entry(status).or_default() += 1;
+        }
+        result.map(|_| ())
+    }
+
+    /// 把发送失败的上报排到队尾，返回带积压数量的错误
+    fn enqueue(&mut self, payload: Value, error: Box<dyn Error>) -> Box<dyn Error> {
+        let Some(queue) = self.queue.as_mut() else {
+            return error;
+        };
+        if let Err(e) = queue.push(payload) {
+            return e;
+        }
+        format!("{} ({} reports queued)", error, queue.len()).into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{Auth, AuthScheme, Secret};
+    use crate::reporter::{JsonLinesReporter, StdoutReporter, WebhookReporter};
+    use crate::test_util::TempDir;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    fn target(reporter: Box<dyn Reporter>) -> Target {
+        let config = TargetConfig {
+            name: "blog".to_string(),
+            ..Default::default()
+        };
+        Target::new(&config, &[], reporter, None, 5)
+    }
+
+    #[test]
+    fn carries_backoff_for_same_kind() {
+        let mut previous = target(Box::new(StdoutReporter));
+        previous.health.failure_count = 4;
+        previous.consecutive_failures = 3;
+        previous.retry_at = Some(Utc::now() + chrono::Duration::seconds(40));
+        previous.in_schedule = Some(false);
+
+        let mut reloaded = target(Box::new(StdoutReporter));
+        reloaded.carry_over(&previous);
+        assert_eq!(reloaded.health.failure_count, 4);
+        assert_eq!(reloaded.consecutive_failures, 3);
+        assert_eq!(reloaded.retry_at, previous.retry_at);
+        assert_eq!(reloaded.in_schedule, Some(false));
+
+        // 换了上报方式时重新开始重试，统计照常保留
+        let mut changed = target(Box::new(JsonLinesReporter { path: "/dev/null".into() }));
+        changed.carry_over(&previous);
+        assert_eq!(changed.health.failure_count, 4);
+        assert_eq!(changed.consecutive_failures, 0);
+        assert_eq!(changed.retry_at, None);
+        assert_eq!(changed.in_schedule, None);
+    }
+
+    /// 本地的假 Webhook：down 时返回 503，否则记录请求体并返回 200
+    async fn mock_server(down: Arc<AtomicBool>, received: Arc<Mutex<Vec<Value>>>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).await.unwrap();
+                let status = if down.load(Ordering::SeqCst) {
+                    "503 Service Unavailable"
+                } else {
+                    received.lock().unwrap().push(serde_json::from_slice(&body).unwrap());
+                    "200 OK"
+                };
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+                reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    fn activity(process: &str, timestamp: i64) -> Activity {
+        Activity {
+            process: process.to_string(),
+            extend: String::new(),
+            media: None,
+            timestamp,
+            window: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn replays_queue_in_order_when_endpoint_recovers() {
+        let down = Arc::new(AtomicBool::new(true));
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let url = mock_server(down.clone(), received.clone()).await;
+
+        let dir = TempDir::new("replay");
+        let queue = OfflineQueue::open(&dir.join("queue-blog.jsonl"), 100, 3600).unwrap();
+        let reporter = WebhookReporter {
+            url,
+            method: "POST".to_string(),
+            headers: Vec::new(),
+            auth: Auth {
+                scheme: AuthScheme::None,
+                key: Secret::default(),
+            },
+            timeout: Duration::from_secs(5),
+        };
+        let mut target = target(Box::new(reporter));
+        target.queue = Some(queue);
+
+        // 第一次失败后进入退避，第二次不访问网络直接排队
+        target.report(&activity("Code", 1)).await;
+        target.report(&activity("Firefox", 2)).await;
+        assert_eq!(target.health.queued, 2);
+        assert_eq!(target.health.failure_count, 1);
+        assert!(received.lock().unwrap().is_empty());
+
+        // 恢复后先按顺序重发积压的上报，再发送新的
+        down.store(false, Ordering::SeqCst);
+        target.retry_at = None;
+        target.report(&activity("Telegram", 3)).await;
+        let processes: Vec<Value> = received.lock().unwrap().iter().map(|body| body["process"].clone()).collect();
+        assert_eq!(processes, ["Code", "Firefox", "Telegram"]);
+        assert_eq!(target.health.queued, 0);
+        assert_eq!(target.consecutive_failures, 0);
+
+        let reopened = OfflineQueue::open(&dir.join("queue-blog.jsonl"), 100, 3600).unwrap();
+        assert!(reopened.is_empty());
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_hung_endpoint() {
+        // 接受连接但从不回复
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut connections = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                connections.push(stream);
+            }
+        });
+
+        let reporter = WebhookReporter {
+            url,
+            method: "POST".to_string(),
+            headers: Vec::new(),
+            auth: Auth {
+                scheme: AuthScheme::None,
+                key: Secret::default(),
+            },
+            timeout: Duration::from_secs(30),
+        };
+        let mut target = target(Box::new(reporter));
+        target.timeout = Duration::from_millis(200);
+
+        let started = Instant::now();
+        target.report(&activity("Code", 1)).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(target.health.failure_count, 1);
+        assert!(target.health.last_error.as_deref().unwrap().contains("timed out"));
+    }
+}
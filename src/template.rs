@@ -0,0 +1,67 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/template.rs
+ */
+
+// 简单的 JSON 模板：字符串中的 `{{path}}` 会被替换为上下文中对应的值。
+// 如果字符串只包含一个占位符（例如 `"{{media}}"`），会直接替换成原始 JSON 值，
+// 保留数字、对象等类型；否则按文本插值。
+
+use serde_json::Value;
+
+fn lookup<'a>(context: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(context, |value, key| value.get(key))
+}
+
+fn value_to_text(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(text)) => text.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+/// 对文本做占位符插值
+pub fn render_str(template: &str, context: &Value) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        let path = rest[start + 2..start + 2 + len].trim();
+        output.push_str(&value_to_text(lookup(context, path)));
+        rest = &rest[start + 2 + len + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// 递归渲染 JSON 模板
+pub fn render(template: &Value, context: &Value) -> Value {
+    match template {
+        Value::String(text) => {
+            let trimmed = text.trim();
+            let whole = trimmed
+                .strip_prefix("{{")
+                .and_then(|inner| inner.strip_suffix("}}"))
+                .filter(|inner| !inner.contains("{{") && !inner.contains("}}"));
+            match whole {
+                Some(path) => lookup(context, path.trim()).cloned().unwrap_or(Value::Null),
+                None => Value::String(render_str(text, context)),
+            }
+        }
+        Value::Array(items) => Value::Array(items.iter().map(|item| render(item, context)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| (key.clone(), render(value, context)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
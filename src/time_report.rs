@@ -0,0 +1,326 @@
+/*
+ * @Author: grtsinry43
+ * @Date: 2026-10-18
+ * @FilePath: /processforlinux/src/time_report.rs
+ */
+
+use crate::config::Config;
+use crate::extend::format_duration;
+use crate::history::{self, Span};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, TimeZone, Timelike, Utc};
+use clap::ArgMatches;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// 没有归类的应用
+const UNCATEGORIZED: &str = "Other";
+
+/// 报表的一行
+struct Row {
+    name: String,
+    seconds: i64,
+}
+
+/// 解析时间范围，返回 [from, to] 两个本地日期（都包含）
+pub fn date_range(matches: &ArgMatches) -> Result<(NaiveDate, NaiveDate), Box<dyn Error>> {
+    let today = Local::now().date_naive();
+    let parse = |name: &str| -> Result<Option<NaiveDate>, Box<dyn Error>> {
+        match matches.get_one::<String>(name) {
+            Some(value) => Ok(Some(
+                NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                    .map_err(|e| format!("--{} expects YYYY-MM-DD, got '{}': {}", name, value, e))?,
+            )),
+            None => Ok(None),
+        }
+    };
+
+    let (from, to) = match (parse("from")?, parse("to")?) {
+        (Some(from), to) => (from, to.unwrap_or(today)),
+        (None, Some(to)) => (to, to),
+        (None, None) => {
+            let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+            match matches.get_one::<String>("range").map(String::as_str) {
+                Some("yesterday") => (today - Duration::days(1), today - Duration::days(1)),
+                Some("week") => (monday, today),
+                Some("last-week") => (monday - Duration::days(7), monday - Duration::days(1)),
+                _ => (today, today),
+            }
+        }
+    };
+    if from > to {
+        return Err(format!("--from {} is after --to {}", from, to).into());
+    }
+    Ok((from, to))
+}
+
+/// 本地日期零点对应的 UTC 时间
+pub fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+}
+
+/// 时间段在报表中显示的应用名，未知应用使用窗口类名
+fn app_name(span: &Span) -> &str {
+    if span.app.is_empty() {
+        &span.class
+    } else {
+        &span.app
+    }
+}
+
+/// 按 [categories] 归类，应用名或窗口类名相同（不区分大小写）即属于该类
+fn category<'a>(span: &Span, categories: &'a BTreeMap<String, Vec<String>>) -> &'a str {
+    categories
+        .iter()
+        .find(|(_, apps)| {
+            apps.iter()
+                .any(|app| app.eq_ignore_ascii_case(&span.app) || app.eq_ignore_ascii_case(&span.class))
+        })
+        .map_or(UNCATEGORIZED, |(name, _)| name.as_str())
+}
+
+/// 把时间段截到 [start, end) 之内，并在每个整点处切开
+fn clip_by_hour(span: &Span, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<(DateTime<Utc>, i64)> {
+    let mut pieces = Vec::new();
+    let mut from = span.start.max(start);
+    let until = span.end.min(end);
+    while from < until {
+        let local = from.with_timezone(&Local);
+        let hour_start = local
+            .with_minute(0)
+            .and_then(|time| time.with_second(0))
+            .and_then(|time| time.with_nanosecond(0))
+            .unwrap_or(local);
+        let next_hour = (hour_start + Duration::hours(1)).with_timezone(&Utc);
+        let piece_end = until.min(next_hour);
+        pieces.push((hour_start.with_timezone(&Utc), (piece_end - from).num_seconds()));
+        from = piece_end;
+    }
+    pieces
+}
+
+fn aggregate(spans: &[Span], config: &Config, group: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<Row> {
+    let mut totals: BTreeMap<String, i64> = BTreeMap::new();
+    for span in spans {
+        for (hour, seconds) in clip_by_hour(span, start, end) {
+            let key = match group {
+                "category" => category(span, &config.categories).to_string(),
+                "hour" => hour.with_timezone(&Local).format("%Y-%m-%d %H:00").to_string(),
+                _ => app_name(span).to_string(),
+            };
+            *totals.entry(key).or_default() += seconds;
+        }
+    }
+
+    let mut rows: Vec<Row> = totals
+        .into_iter()
+        .filter(|(_, seconds)| *seconds > 0)
+        .map(|(name, seconds)| Row { name, seconds })
+        .collect();
+    // 按小时分组时按时间排序，其他按时长从多到少
+    if group != "hour" {
+        rows.sort_by(|a, b| b.seconds.cmp(&a.seconds).then_with(|| a.name.cmp(&b.name)));
+    }
+    rows
+}
+
+fn share(seconds: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        seconds as f64 * 100.0 / total as f64
+    }
+}
+
+/// report 子命令：按应用、分类或小时统计本地历史中的时间
+pub fn run(matches: &ArgMatches, config: &Config) -> Result<(), Box<dyn Error>> {
+    let (from, to) = date_range(matches)?;
+    let group = matches.get_one::<String>("by").map_or("app", String::as_str);
+    let format = matches.get_one::<String>("format").map_or("table", String::as_str);
+
+    let next_day = to.succ_opt().ok_or_else(|| format!("--to {} is out of range", to))?;
+    let spans = history::read(&config.history.dir(), from, to)?;
+    let start = local_midnight(from);
+    let end = local_midnight(next_day).min(Utc::now());
+    let rows = aggregate(&spans, config, group, start, end);
+    let total: i64 = rows.iter().map(|row| row.seconds).sum();
+
+    let period = if from == to {
+        from.to_string()
+    } else {
+        format!("{} – {}", from, to)
+    };
+    let column = match group {
+        "category" => "Category",
+        "hour" => "Hour",
+        _ => "App",
+    };
+
+    match format {
+        "json" => {
+            let output = json!({
+                "from": from.to_string(),
+                "to": to.to_string(),
+                "group": group,
+                "total_seconds": total,
+                "rows": rows.iter().map(|row| json!({
+                    "name": row.name,
+                    "seconds": row.seconds,
+                    "share": (share(row.seconds, total) * 10.0).round() / 10.0,
+                })).collect::<Vec<_>>(),
+            });
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        }
+        "csv" => {
+            println!("{},seconds,duration,share", column.to_lowercase());
+            for row in &rows {
+                println!(
+                    "{},{},{},{:.1}",
+                    csv_field(&row.name),
+                    row.seconds,
+                    format_duration(Duration::seconds(row.seconds)),
+                    share(row.seconds, total)
+                );
+            }
+        }
+        "markdown" => {
+            println!("**{}** · total {}", period, format_duration(Duration::seconds(total)));
+            println!();
+            println!("| {} | Time | Share |", column);
+            println!("| --- | ---: | ---: |");
+            for row in &rows {
+                println!(
+                    "| {} | {} | {:.1}% |",
+                    row.name.replace('|', "\\|"),
+                    format_duration(Duration::seconds(row.seconds)),
+                    share(row.seconds, total)
+                );
+            }
+        }
+        _ => {
+            println!("{}  (total {})", period, format_duration(Duration::seconds(total)));
+            if rows.is_empty() {
+                println!("No activity recorded in {}", config.history.dir().display());
+                return Ok(());
+            }
+            let width = rows.iter().map(|row| display_width(&row.name)).max().unwrap_or(0).max(column.len());
+            println!("{:<width$}  {:>8}  {:>6}", column, "Time", "Share", width = width);
+            for row in &rows {
+                let padding = width - display_width(&row.name);
+                println!(
+                    "{}{}  {:>8}  {:>5.1}%",
+                    row.name,
+                    " ".repeat(padding),
+                    format_duration(Duration::seconds(row.seconds)),
+                    share(row.seconds, total)
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 终端中的显示宽度，中日韩文字和全角符号占两列
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if ('\u{2E80}'..='\u{FFEF}').contains(&c) { 2 } else { 1 })
+        .sum()
+}
+
+/// 含逗号、引号或换行的字段加引号
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 本地时间 2026-01-15 的某个整点，按本地时区切小时
+    fn local(hour: u32, minute: u32) -> DateTime<Utc> {
+        Local
+            .with_ymd_and_hms(2026, 1, 15, hour, minute, 0)
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn span(app: &str, class: &str, start: DateTime<Utc>, end: DateTime<Utc>) -> Span {
+        Span {
+            start,
+            end,
+            app: app.to_string(),
+            class: class.to_string(),
+            exe: String::new(),
+            title: String::new(),
+            media: None,
+        }
+    }
+
+    #[test]
+    fn splits_spans_at_hour_boundaries() {
+        let span = span("Code", "code", local(9, 40), local(11, 15));
+        assert_eq!(
+            clip_by_hour(&span, local(0, 0), local(23, 0)),
+            vec![(local(9, 0), 20 * 60), (local(10, 0), 60 * 60), (local(11, 0), 15 * 60)]
+        );
+    }
+
+    #[test]
+    fn clips_spans_to_range() {
+        let span = span("Code", "code", local(9, 40), local(11, 15));
+        assert_eq!(
+            clip_by_hour(&span, local(10, 30), local(11, 10)),
+            vec![(local(10, 0), 30 * 60), (local(11, 0), 10 * 60)]
+        );
+        assert!(clip_by_hour(&span, local(12, 0), local(13, 0)).is_empty());
+        assert!(clip_by_hour(&span, local(11, 15), local(12, 0)).is_empty());
+    }
+
+    #[test]
+    fn groups_by_app_category_and_hour() {
+        let mut config = Config::default();
+        config.categories.insert("Work".to_string(), vec!["code".to_string()]);
+        let spans = [
+            span("Code", "code", local(9, 50), local(10, 20)),
+            span("", "kitty", local(10, 20), local(10, 30)),
+            span("Firefox", "firefox", local(10, 30), local(10, 35)),
+        ];
+        let rows = |group| {
+            aggregate(&spans, &config, group, local(0, 0), local(23, 0))
+                .into_iter()
+                .map(|row| (row.name, row.seconds))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            rows("app"),
+            vec![("Code".to_string(), 1800), ("kitty".to_string(), 600), ("Firefox".to_string(), 300)]
+        );
+        assert_eq!(rows("category"), vec![("Work".to_string(), 1800), ("Other".to_string(), 900)]);
+        let hour = |time: DateTime<Utc>| time.with_timezone(&Local).format("%Y-%m-%d %H:00").to_string();
+        assert_eq!(rows("hour"), vec![(hour(local(9, 0)), 600), (hour(local(10, 0)), 2100)]);
+    }
+
+    #[test]
+    fn quotes_csv_fields() {
+        assert_eq!(csv_field("Code"), "Code");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn counts_wide_characters_twice() {
+        assert_eq!(display_width("Code"), 4);
+        assert_eq!(display_width("微信"), 4);
+    }
+}